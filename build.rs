@@ -0,0 +1,18 @@
+// The menu ROM is built into the binary (see MENU_ROM in main.rs), so catch a missing or
+// oversized one here rather than with a confusing include_bytes! error
+const MENU_ROM: &str = "roms/menu-new.ch8";
+
+// 4KB of memory, less the 512 bytes below 0x200
+const MAX_ROM_SIZE: u64 = 4096 - 0x200;
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", MENU_ROM);
+
+    let size = match std::fs::metadata(MENU_ROM) {
+        Ok(meta) => meta.len(),
+        Err(err) => panic!("menu ROM {} can't be embedded: {}", MENU_ROM, err)
+    };
+    if size == 0 || size > MAX_ROM_SIZE {
+        panic!("menu ROM {} is {} bytes, it has to be between 1 and {}", MENU_ROM, size, MAX_ROM_SIZE);
+    }
+}
@@ -0,0 +1,108 @@
+use rppal::gpio::OutputPin;
+use rppal::pwm::{Channel, Polarity, Pwm};
+
+// Concert A, until something (XO-CHIP's pitch register) asks for another tone
+pub const DEFAULT_TONE_HZ: f64 = 440.0;
+// Square wave
+const TONE_DUTY_CYCLE: f64 = 0.5;
+
+// Where the emulator sends sound timer on/off, so the buzzer can be swapped out
+pub trait AudioOutput {
+    fn play(&mut self);
+    fn stop(&mut self);
+    fn is_playing(&self) -> bool;
+}
+
+// Buzzer driven straight from a GPIO pin, on while the sound timer is running
+pub struct GpioBuzzer {
+    pin: OutputPin,
+    playing: bool
+}
+
+impl GpioBuzzer {
+    pub fn new(pin: OutputPin) -> Self {
+        GpioBuzzer { pin, playing: false }
+    }
+}
+
+impl AudioOutput for GpioBuzzer {
+    fn play(&mut self) {
+        self.pin.set_high();
+        self.playing = true;
+    }
+
+    fn stop(&mut self) {
+        self.pin.set_low();
+        self.playing = false;
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
+
+// Square wave from the hardware PWM on PWM0 (GPIO 12 or 18), a proper tone rather than the
+// click a GPIO buzzer makes. PWM0 has to be enabled with the pwm overlay in config.txt.
+pub struct PwmBuzzer {
+    pwm: Pwm,
+    frequency_hz: f64,
+    playing: bool
+}
+
+impl PwmBuzzer {
+    pub fn new(frequency_hz: f64) -> Result<Self, rppal::pwm::Error> {
+        let pwm = Pwm::with_frequency(Channel::Pwm0, frequency_hz, TONE_DUTY_CYCLE, Polarity::Normal, false)?;
+        Ok(PwmBuzzer { pwm, frequency_hz, playing: false })
+    }
+
+    pub fn frequency(&self) -> f64 {
+        self.frequency_hz
+    }
+
+    // Takes effect straight away, even mid tone
+    pub fn set_frequency(&mut self, frequency_hz: f64) -> Result<(), rppal::pwm::Error> {
+        self.pwm.set_frequency(frequency_hz, TONE_DUTY_CYCLE)?;
+        self.frequency_hz = frequency_hz;
+        Ok(())
+    }
+}
+
+impl AudioOutput for PwmBuzzer {
+    fn play(&mut self) {
+        match self.pwm.enable() {
+            Ok(()) => self.playing = true,
+            Err(err) => log::warn!("Couldn't start the PWM buzzer: {}", err)
+        }
+    }
+
+    fn stop(&mut self) {
+        match self.pwm.disable() {
+            Ok(()) => self.playing = false,
+            Err(err) => log::warn!("Couldn't stop the PWM buzzer: {}", err)
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
+
+// For builds without a buzzer, and for testing
+#[derive(Default)]
+pub struct SilentAudio {
+    playing: bool
+}
+
+impl AudioOutput for SilentAudio {
+    fn play(&mut self) {
+        self.playing = true;
+    }
+
+    fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
@@ -1,22 +1,80 @@
 use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::{fs::File, io::{self, BufRead}};
-use crate::instruction::Instruction;
-use crate::quirks::Quirks;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use crate::display::{ChipDisplay, DisplayError, DisplayInterface};
+use crate::emulator::EmulatorConfig;
+use crate::error::Chip8Error;
+use crate::framebuffer::FramebufferRenderer;
+use crate::instruction::{Instruction, Opcode, OpcodeClass};
+use crate::keypad::{keys_to_mask, mask_to_keys, InputScript};
+use crate::opcode_handler::{Chip8State, OpcodeHandler};
+use crate::quirks::{IOverflowBehavior, Platform, Quirks, ShiftMode};
+use crate::report::{Chip8Stats, ConfidenceLevel, JumpWarning, JumpWarningKind, TestReport};
+use crate::rom_analyzer::{RomAnalyzer, RomFormat};
 
 // Emulator Cycle Return Values
 const SUCCESSFUL_EXECUTION: u8 = 0;
 const EXIT_ROM: u8 = 1;
 
 // Chip8 Display Constants
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+// SUPER-CHIP high resolution mode (00FF)
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// The debug overlay covers the last SSD1309 page, which holds the bottom 4 Chip8 rows
+const DEBUG_OVERLAY_PAGE: u8 = 7;
+const DEBUG_OVERLAY_START_ROW: usize = 28;
+
+// Audio visualization uses the same bottom page. The Chip8 display keeps rows 0-23 (the top
+// 6 pages), leaving a blank page between it and the waveform.
+const AUDIO_VISUALIZATION_PAGE: u8 = 7;
+const AUDIO_VISUALIZATION_ROWS: usize = 24;
 
 // Chip8 Memory Constants
 const FONTSET_START: usize = 0x50;
-const ROM_START: usize = 0x200;
+const BIG_FONTSET_START: usize = 0xA0;
+pub(crate) const ROM_START: usize = 0x200;
+pub const ROM_LOAD_CHIP8: u16 = 0x200;
+pub const ROM_LOAD_ETI660: u16 = 0x600;
+const DEFAULT_MEMORY_SIZE: usize = 4096;
+
+// Save state header, bump the version whenever the layout changes
+const SAVE_STATE_MAGIC: &[u8; 4] = b"CH8S";
+const SAVE_STATE_VERSION: u8 = 2;
+const SUPPORTED_MEMORY_SIZES: [usize; 3] = [4096, 32768, 65536];
+
+// The HP48 only had 8 RPL user flags for FX75/FX85
+pub const RPL_FLAG_COUNT: usize = 8;
+
+// Chip8 Timing Constants
+const VBLANK_INTERVAL_US: u64 = 1_000_000 / 60;
+pub const DEFAULT_TIMER_HZ: u64 = 60;
+const TIMER_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / DEFAULT_TIMER_HZ);
+
+// Starting value of the display hash, what an empty display hashes to
+const DISPLAY_HASH_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// Instructions per second step_one_frame aims for unless told otherwise
+const DEFAULT_TARGET_IPS: u64 = 700;
 
-const FONTSET: [u8; 80] = [
+// XO-CHIP pitch register default (4000Hz playback rate)
+const DEFAULT_AUDIO_PITCH: u8 = 64;
+
+// Instruction groups by leading nibble, for the histogram
+const NIBBLE_NAMES: [&str; 16] = [
+    "0 SYS", "1 JP", "2 CALL", "3 SE", "4 SNE", "5 SE VY", "6 LD", "7 ADD",
+    "8 ALU", "9 SNE VY", "A LD I", "B JP V0", "C RND", "D DRW", "E SKP", "F MISC"
+];
+
+pub const DEFAULT_FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -35,466 +93,3889 @@ const FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// SCHIP 8x10 digits for FX30, there are no large A-F
+const BIG_FONTSET: [u8; 100] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF  // 9
+];
+
+pub struct StressResult {
+    pub total: u64,
+    pub errors: HashMap<String, u64>,
+    pub final_memory_ok: bool
+}
+
+pub struct CycleContext {
+    // Flips every time a cycle draws to the display
+    pub frame_parity: u8
+}
+
+#[derive(Debug)]
+pub struct FrameResult {
+    pub cycles_run: u32,
+    pub drew: bool,
+    pub sound_active: bool,
+    // V1 if the ROM exited during the frame
+    pub exit_code: Option<u8>,
+    pub error: Option<Chip8Error>
+}
+
+pub struct CycleResult {
+    pub drew: bool,
+    pub sound_changed: bool,
+    pub jumped: bool,
+    pub exited: bool,
+    pub step: StepResult
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RomFingerprint {
+    pub sha256: [u8; 32],
+    pub size: usize,
+    pub first_instruction: u16,
+    pub jump_count: u32,
+    pub draw_count: u32,
+    pub uses_schip: bool,
+    pub uses_xochip: bool
+}
+
+pub struct DelayTestResult {
+    pub expected_ms: u64,
+    pub actual_ms: u64,
+    pub tick_count: u32,
+    pub drift_pct: f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegWrite {
+    pub reg: u8,
+    pub old_val: u8,
+    pub new_val: u8,
+    pub pc: u16
+}
+
+// Where time went while profiling, in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionTimeline {
+    pub fetch_us_total: u64,
+    pub execute_us_total: u64,
+    pub display_us_total: u64,
+    pub timer_us_total: u64,
+    pub sleep_us_total: u64,
+    pub cycles: u64
+}
+
+impl ExecutionTimeline {
+    pub fn total_us(&self) -> u64 {
+        self.fetch_us_total + self.execute_us_total + self.display_us_total + self.timer_us_total + self.sleep_us_total
+    }
+
+    // Fetch, execute, display, timer and sleep as percentages of the total
+    pub fn percentages(&self) -> [f64; 5] {
+        let total = self.total_us().max(1) as f64;
+        [self.fetch_us_total, self.execute_us_total, self.display_us_total, self.timer_us_total, self.sleep_us_total]
+            .map(|us| us as f64 / total * 100.0)
+    }
+}
+
+impl fmt::Display for ExecutionTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [fetch, execute, display, timer, sleep] = self.percentages();
+        write!(f, "Fetch: {:.1}%, Execute: {:.1}%, Display: {:.1}%, Timer: {:.1}%, Sleep: {:.1}%",
+            fetch, execute, display, timer, sleep)
+    }
+}
+
+// How often each opcode ran while profiling, by top nibble and by opcode with its register
+// and immediate operands masked off, so e.g. every 8XY4 counts together
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileData {
+    pub nibble_counts: [u64; 16],
+    pub full_counts: HashMap<u16, u64>
+}
+
+impl ProfileData {
+    fn record(&mut self, raw: u16) {
+        self.nibble_counts[(raw >> 12) as usize] += 1;
+        *self.full_counts.entry(Self::opcode_group(raw)).or_insert(0) += 1;
+    }
+
+    // Keeps only the bits that pick the instruction
+    pub fn opcode_group(raw: u16) -> u16 {
+        match raw >> 12 {
+            0x0 if raw & 0xFFF0 == 0x00C0 => raw & 0xFFF0,
+            0x0 => raw,
+            0x5 | 0x8 | 0x9 => raw & 0xF00F,
+            0xE | 0xF => raw & 0xF0FF,
+            _ => raw & 0xF000
+        }
+    }
+
+    // Operand nibbles written as X, Y and N the way opcode tables usually show them
+    fn group_pattern(group: u16) -> String {
+        let nibble = group >> 12;
+        match nibble {
+            0x0 if group == 0x00C0 => "00CN".to_string(),
+            0x0 => format!("{:04X}", group),
+            0x1 | 0x2 | 0xA | 0xB => format!("{:X}NNN", nibble),
+            0x3 | 0x4 | 0x6 | 0x7 | 0xC => format!("{:X}XNN", nibble),
+            0x5 | 0x8 | 0x9 => format!("{:X}XY{:X}", nibble, group & 0xF),
+            0xD => "DXYN".to_string(),
+            _ => format!("{:X}X{:02X}", nibble, group & 0xFF)
+        }
+    }
+
+    // Most used first, ties in opcode order
+    pub fn report(&self) -> String {
+        let total = self.full_counts.values().sum::<u64>().max(1) as f64;
+        let mut groups: Vec<(u16, u64)> = self.full_counts.iter().map(|(&group, &count)| (group, count)).collect();
+        groups.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut out = String::from("Opcode  Count       %\n");
+        for (group, count) in groups {
+            out.push_str(&format!("{:<6}  {:<10}  {:.1}\n", Self::group_pattern(group), count, count as f64 / total * 100.0));
+        }
+        out
+    }
+}
+
+// Spread of the time between cycles, in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JitterStats {
+    pub mean_us: f64,
+    pub stddev_us: f64,
+    pub min_us: u32,
+    pub max_us: u32,
+    pub p99_us: u32
+}
+
+impl JitterStats {
+    pub fn from_samples(samples: &[u32]) -> JitterStats {
+        if samples.is_empty() {
+            return JitterStats::default();
+        }
+
+        let count = samples.len() as f64;
+        let mean_us = samples.iter().map(|&us| us as f64).sum::<f64>() / count;
+        let variance = samples.iter().map(|&us| (us as f64 - mean_us).powi(2)).sum::<f64>() / count;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let p99_index = (sorted.len() * 99).div_ceil(100) - 1;
+
+        JitterStats {
+            mean_us,
+            stddev_us: variance.sqrt(),
+            min_us: sorted[0],
+            max_us: sorted[sorted.len() - 1],
+            p99_us: sorted[p99_index]
+        }
+    }
+}
+
+impl fmt::Display for JitterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cycle time: mean {:.1}us, stddev {:.1}us, min {}us, max {}us, p99 {}us",
+            self.mean_us, self.stddev_us, self.min_us, self.max_us, self.p99_us)
+    }
+}
+
+// Phases of the game loop that happen outside of Chip8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfilePhase {
+    Display,
+    Sleep
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateComparison {
+    pub registers_match: bool,
+    pub display_match: bool,
+    pub pc_match: bool
+}
+
+// What cycle() does after an error, as decided by the error recovery handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryAction {
+    // Return the error as usual
+    Abort,
+    // Carry on from the instruction after the one that failed
+    SkipInstruction,
+    // Soft reset, the ROM starts over
+    Reset,
+    // Carry on with the state the error left behind
+    Ignore
+}
+
+// Machine state compared by compare_to_reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceState {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub memory: Vec<u8>,
+    pub display: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergencePoint {
+    pub cycle: u64,
+    pub instruction: u16,
+    pub self_state: ReferenceState,
+    pub other_state: ReferenceState
+}
+
+// A two instruction loop polling a key, SKP/SKNP VX followed by a jump back to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinWaitInfo {
+    pub loop_pc: u16,
+    pub key: u8
+}
+
+// What a ROM uses a register for, shown next to the register in debug output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterAlias {
+    pub reg: u8,
+    pub name: &'static str,
+    pub purpose: &'static str
+}
+
+const REGISTER_NAMES: [&str; 16] = [
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7",
+    "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF"
+];
+
+// Machine state right after an instruction ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub raw: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u8
+}
+
+// Condition that stops cycle() with WatchpointHit. Register ones fire when an instruction writes
+// the value to VX, Memory ones when an instruction changes the byte to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    Register { reg: usize, value: u8 },
+    Memory { addr: u16, value: u8 }
+}
+
+// How a step() went. Stopping for the debugger or the ROM ending aren't errors here,
+// unlike with cycle().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    Continue,
+    Halted,
+    BreakpointHit(u16),
+    WatchpointHit(Watchpoint),
+    ExitRom
+}
+
+impl StepResult {
+    // Back to what cycle() would have returned
+    fn into_cycle_result(self) -> Result<u8, Chip8Error> {
+        match self {
+            StepResult::Continue => Ok(SUCCESSFUL_EXECUTION),
+            StepResult::ExitRom => Ok(EXIT_ROM),
+            StepResult::Halted => Err(Chip8Error::Halted),
+            StepResult::BreakpointHit(addr) => Err(Chip8Error::BreakpointHit(addr)),
+            StepResult::WatchpointHit(watchpoint) => Err(Chip8Error::WatchpointHit { watchpoint })
+        }
+    }
+}
+
+// Something an instruction changed, as seen by execute_all_from_pc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChange {
+    Register { reg: u8, old: u8, new: u8 },
+    Index { old: u16, new: u16 },
+    StackPointer { old: u8, new: u8 },
+    Memory { addr: u16, old: u8, new: u8 },
+    DelayTimer { old: u8, new: u8 },
+    SoundTimer { old: u8, new: u8 },
+    Display { pixels_changed: u32 }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionRecord {
+    pub pc: u16,
+    pub raw: u16,
+    pub mnemonic: String,
+    pub changed_fields: Vec<StateChange>
+}
+
 pub struct Chip8 {
-    pub memory: [u8; 4096],
+    pub memory: Vec<u8>,
     pub v: [u8; 16],
     i: u16,
     pc: u16,
-    pub display: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    pub(crate) display: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    // XO-CHIP second bitplane, display doubles as the first one
+    plane2: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    hires: bool,
+    hires_display: [[bool; HIRES_WIDTH]; HIRES_HEIGHT],
+    plane_mask: u8,
+    display_hash: u64,
     stack: [u16; 16],
     sp: usize,
     pub delay_timer: u8,
     pub sound_timer: u8,
+    timer_debt: u8,
+    timer_remainder: Duration,
+    // Time between delay/sound timer ticks, 60Hz unless set_timer_hz says otherwise
+    timer_interval: Duration,
+    last_timer_tick: Instant,
+    sound_timer_total: u64,
+    audio_pitch: u8,
+    audio_pattern: [u8; 16],
+    // SCHIP FX75/FX85 user flags, kept through resets
+    rpl_flags: [u8; RPL_FLAG_COUNT],
     pub keypad: [bool; 16],
+    rom_size: usize,
+    // Where the loaded ROM starts, and where execution starts from
+    rom_start: usize,
+    applied_patches: Vec<(u16, u8)>,
+    labels: BTreeMap<u16, &'static str>,
+    rom_checksum: Option<u32>,
     pub draw_flag: bool,
+    display_changed: bool,
     wait_for_release: bool,
     wait_key: usize,
+    last_instruction: Option<u16>,
+    // Where the instruction being executed was fetched from, for register write records
+    fetch_pc: u16,
+    collision_count: u64,
+    frames_rendered: u64,
+    pause_on_frame: Option<u64>,
+    pause_on_cycle: Option<u64>,
+    draws_this_frame: u8,
+
+    // Display/input lag, both delayed by whole timer ticks through these queues
+    display_lag_frames: u8,
+    input_lag_frames: u8,
+    pending_draw: bool,
+    display_lag: VecDeque<bool>,
+    latest_keypad: [bool; 16],
+    // Scripted keypad changes, played back by cycle number, and the one being recorded
+    input_script: Option<InputScript>,
+    input_script_pos: usize,
+    input_recording: Option<InputScript>,
+    input_lag: VecDeque<[bool; 16]>,
+    cycles: u64,
+    // Unlike cycles this one survives resets
+    total_cycles: u64,
+    target_ips: u64,
+    recent_pcs: [u16; 4],
+    spin_wait_count: u64,
+    nmi_count: u64,
+    instruction_histogram: [u64; 16],
+    opcodes_executed: HashSet<OpcodeClass>,
+    call_depth_histogram: [u32; 16],
+    max_observed_sp: usize,
     rng: ChaCha8Rng,
+    frame_start: Instant,
+    // What the display wait quirk sleeps with, swapped out to test it without waiting
+    sleep_fn: fn(Duration),
+
+    // Replace the font addresses FX29/FX30 point I at, given the digit
+    fx29_override: Option<fn(u8) -> u16>,
+    fx30_override: Option<fn(u8) -> u16>,
+
+    // User-defined opcodes, checked before the built-in instruction set
+    extensions: HashMap<u16, Box<dyn OpcodeHandler>>,
 
-    // Quirks
+    // Quirks, and the platform deciding which extended opcodes exist
     quirks: Quirks,
+    platform: Platform,
+
+    // Register write tracing (capacity 0 means disabled)
+    register_trace: VecDeque<RegWrite>,
+    register_trace_cap: usize,
+    register_hook: Option<fn(u8, u8, u8, u16)>,
+    watchpoints: Vec<Watchpoint>,
+    // Register watchpoint the current instruction wrote, checked once execute is done
+    register_watch_hit: Option<Watchpoint>,
+
+    register_aliases: [Option<RegisterAlias>; 16],
+
+    error_recovery: Option<fn(&Chip8Error, &mut Chip8) -> RecoveryAction>,
+
+    // Called with the display after a cycle that changed it, by the async loop
+    draw_hook: Option<fn(&[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT])>,
+
+    // Full instruction trace (capacity 0 means disabled)
+    trace_log: VecDeque<TraceEntry>,
+    trace_log_cap: usize,
+
+    // Timing breakdown and opcode counts, only collected while profiling is on
+    profiling: bool,
+    timeline: ExecutionTimeline,
+    profile: Option<Box<ProfileData>>,
+    last_cycle_at: Option<Instant>,
+    cycle_deltas: VecDeque<u32>,
+
+    // Pause instead of running unknown opcodes, keeping the last one for inspection
+    break_on_unknown: bool,
+    // A RET with nothing on the stack exits the ROM (how the menu hands back its choice)
+    wait_for_initial_ret: bool,
+    last_unknown_opcode: Option<u16>,
+
+    // cycle() stops before running anything at these addresses, skip_breakpoint lets one through
+    breakpoints: BTreeSet<u16>,
+    skip_breakpoint: bool,
+
+    // Cleared by halt() to stop cycle() from running anything
+    running: bool,
+
+    // Set while fast forwarding, hooks, debug output and display waits are skipped
+    pub fast_forward_mode: bool,
 
     // Debug
     pub debug: bool,
+    pub debug_overlay_enabled: bool,
+
+    // Audio visualization, draws are limited to the first display_rows rows
+    audio_visualization: bool,
+    visualized_pattern: Option<[u8; 16]>,
+    display_rows: usize,
     pub paused: bool
 }
 
+pub struct Chip8Builder {
+    quirks: Quirks,
+    platform: Platform,
+    memory_size: usize
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8Builder {
+    pub fn new() -> Self {
+        Chip8Builder {
+            quirks: Quirks::default(),
+            platform: Platform::Chip8,
+            memory_size: DEFAULT_MEMORY_SIZE
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // Which extended opcode families exist, the quirks are left as they are
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    // 4096 for standard Chip8, 32768 or 65536 for extended memory variants
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, Chip8Error> {
+        if !SUPPORTED_MEMORY_SIZES.contains(&self.memory_size) {
+            return Err(Chip8Error::InvalidMemorySize(self.memory_size));
+        }
+        Ok(Chip8::with_config(self.quirks, self.platform, self.memory_size))
+    }
+}
+
 impl Chip8 {
     pub fn new(quirks: Quirks) -> Self {
+        Self::with_config(quirks, Platform::Chip8, DEFAULT_MEMORY_SIZE)
+    }
+
+    // Same random sequence every run, for tests and replays
+    pub fn new_with_seed(quirks: Quirks, seed: [u8; 32]) -> Self {
+        let mut chip8 = Self::new(quirks);
+        chip8.rng = ChaCha8Rng::from_seed(seed);
+        chip8
+    }
+
+    pub fn with_memory_size(size: usize) -> Chip8Builder {
+        Chip8Builder::new().memory_size(size)
+    }
+
+    // Games play out differently every run, unless there's no OS entropy to draw on
+    fn initial_seed() -> [u8; 32] {
+        #[cfg(feature = "rand-os")]
+        let seed = rand::random();
+        #[cfg(not(feature = "rand-os"))]
+        let seed = [0; 32];
+        seed
+    }
+
+    pub fn seed(&self) -> [u8; 32] {
+        self.rng.get_seed()
+    }
+
+    fn with_config(quirks: Quirks, platform: Platform, memory_size: usize) -> Self {
         let mut chip8 = Chip8 {
-            memory: [0; 4096],
+            memory: vec![0; memory_size],
             v: [0; 16],
             i: 0,
             pc: 0x200,
             display: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            plane2: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            hires: false,
+            hires_display: [[false; HIRES_WIDTH]; HIRES_HEIGHT],
+            plane_mask: 1,
+            display_hash: DISPLAY_HASH_SEED,
             stack: [0; 16],
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
+            timer_debt: 0,
+            timer_remainder: Duration::ZERO,
+            timer_interval: TIMER_INTERVAL,
+            last_timer_tick: Instant::now(),
+            sound_timer_total: 0,
+            audio_pitch: DEFAULT_AUDIO_PITCH,
+            audio_pattern: [0; 16],
+            rpl_flags: [0; RPL_FLAG_COUNT],
             keypad: [false; 16],
+            rom_size: 0,
+            rom_start: ROM_START,
+            applied_patches: Vec::new(),
+            labels: BTreeMap::new(),
+            rom_checksum: None,
             draw_flag: false,
+            display_changed: false,
             wait_for_release: false,
             wait_key: 0,
-            rng: ChaCha8Rng::from_seed(Default::default()),
-            quirks: quirks,
+            last_instruction: None,
+            fetch_pc: 0,
+            collision_count: 0,
+            frames_rendered: 0,
+            pause_on_frame: None,
+            pause_on_cycle: None,
+            draws_this_frame: 0,
+            display_lag_frames: 0,
+            input_lag_frames: 0,
+            pending_draw: false,
+            display_lag: VecDeque::new(),
+            latest_keypad: [false; 16],
+            input_script: None,
+            input_script_pos: 0,
+            input_recording: None,
+            input_lag: VecDeque::new(),
+            cycles: 0,
+            total_cycles: 0,
+            target_ips: DEFAULT_TARGET_IPS,
+            recent_pcs: [0; 4],
+            spin_wait_count: 0,
+            nmi_count: 0,
+            instruction_histogram: [0; 16],
+            opcodes_executed: HashSet::new(),
+            call_depth_histogram: [0; 16],
+            max_observed_sp: 0,
+            rng: ChaCha8Rng::from_seed(Self::initial_seed()),
+            frame_start: Instant::now(),
+            sleep_fn: sleep,
+            fx29_override: None,
+            fx30_override: None,
+            extensions: HashMap::new(),
+            quirks,
+            platform,
+
+            register_trace: VecDeque::new(),
+            register_trace_cap: 0,
+            register_hook: None,
+            watchpoints: Vec::new(),
+            register_watch_hit: None,
+            register_aliases: [None; 16],
+            error_recovery: None,
+            draw_hook: None,
+            trace_log: VecDeque::new(),
+            trace_log_cap: 0,
+            profiling: false,
+            timeline: ExecutionTimeline::default(),
+            profile: None,
+            last_cycle_at: None,
+            cycle_deltas: VecDeque::new(),
+            break_on_unknown: false,
+            wait_for_initial_ret: true,
+            last_unknown_opcode: None,
+            breakpoints: BTreeSet::new(),
+            skip_breakpoint: false,
+            running: true,
+            fast_forward_mode: false,
 
             // Debug flags
             debug: false,
+            debug_overlay_enabled: false,
+            audio_visualization: false,
+            visualized_pattern: None,
+            display_rows: DISPLAY_HEIGHT,
             paused: false
         };
 
-        for (i, byte) in FONTSET.iter().enumerate() {
+        for (i, byte) in DEFAULT_FONTSET.iter().enumerate() {
             chip8.memory[FONTSET_START + i] = *byte;
         }
+        chip8.memory[BIG_FONTSET_START..BIG_FONTSET_START + BIG_FONTSET.len()].copy_from_slice(&BIG_FONTSET);
 
         chip8
     }
 
+    // reset() plus wiping memory, for when a different ROM is about to be loaded
+    pub fn hard_reset(&mut self) {
+        self.reset();
+
+        // Wipe memory, including the loaded ROM
+        self.memory.fill(0);
+        self.rom_size = 0;
+        self.rom_start = ROM_START;
+        self.applied_patches.clear();
+        self.labels.clear();
+        self.rom_checksum = None;
+
+        // Reload the font sets
+        for (i, byte) in DEFAULT_FONTSET.iter().enumerate() {
+            self.memory[FONTSET_START + i] = *byte;
+        }
+        self.memory[BIG_FONTSET_START..BIG_FONTSET_START + BIG_FONTSET.len()].copy_from_slice(&BIG_FONTSET);
+    }
+
+    #[deprecated(note = "use reset, which now leaves the loaded ROM in place")]
+    pub fn reset_soft(&mut self) {
+        self.reset();
+    }
+
+    // Reset CPU state, leaving memory (and the loaded ROM) alone so the same ROM can be
+    // played again without reloading it
     pub fn reset(&mut self) {
-        // Reset variables
-        self.memory = [0; 4096];
         self.v = [0; 16];
         self.i = 0;
-        self.pc = 0x200;
+        self.pc = self.rom_start as u16;
         self.display = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        self.plane2 = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        self.hires = false;
+        self.hires_display = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        self.plane_mask = 1;
+        self.display_hash = DISPLAY_HASH_SEED;
         self.stack = [0; 16];
         self.sp = 0;
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.timer_debt = 0;
+        self.timer_remainder = Duration::ZERO;
+        self.last_timer_tick = Instant::now();
+        self.sound_timer_total = 0;
+        self.audio_pitch = DEFAULT_AUDIO_PITCH;
+        self.audio_pattern = [0; 16];
         self.keypad = [false; 16];
         self.draw_flag = false;
+        self.display_changed = false;
         self.wait_for_release = false;
         self.wait_key = 0;
+        self.frame_start = Instant::now();
+        self.last_instruction = None;
+        self.collision_count = 0;
+        self.frames_rendered = 0;
+        self.draws_this_frame = 0;
+        self.pending_draw = false;
+        self.latest_keypad = [false; 16];
+        self.emulate_display_lag(self.display_lag_frames);
+        self.keypad_read_lag(self.input_lag_frames);
+        self.cycles = 0;
+        self.input_script_pos = 0;
+        self.recent_pcs = [0; 4];
+        self.spin_wait_count = 0;
+        self.nmi_count = 0;
+        self.instruction_histogram = [0; 16];
+        self.opcodes_executed.clear();
+        self.call_depth_histogram = [0; 16];
+        self.max_observed_sp = 0;
+        self.last_unknown_opcode = None;
+        self.skip_breakpoint = false;
+        // Restart the same random sequence, so a seeded run plays out the same after a reset
+        self.rng = ChaCha8Rng::from_seed(self.seed());
 
-        // Reload the font set
-        for (i, byte) in FONTSET.iter().enumerate() {
-            self.memory[FONTSET_START + i] = *byte;
+        // Patches stick around until a new ROM is loaded
+        for &(addr, byte) in self.applied_patches.iter() {
+            self.memory[addr as usize] = byte;
         }
     }
 
-    pub fn debug_print(&mut self) {
-        println!("PC: 0x{:X}", self.pc);
-        let mut line: u8 = 0;
-        for register in 0..16 {
-            print!("v{:X}: 0x{:X}  \t", register, self.v[register]);
-            if line >= 4 {
-                print!("\r\n");
-                line = 0;
-            } else {
-                line += 1;
+    // Copy of the emulator that can be run without side effects. Opcode extensions,
+    // hooks, watchpoints and anything that sleeps or prints are left out.
+    pub(crate) fn detached_copy(&self) -> Chip8 {
+        let mut quirks = self.quirks;
+        quirks.display_wait = false;
+
+        Chip8 {
+            memory: self.memory.clone(),
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            display: self.display,
+            plane2: self.plane2,
+            hires: self.hires,
+            hires_display: self.hires_display,
+            plane_mask: self.plane_mask,
+            display_hash: self.display_hash,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            timer_debt: self.timer_debt,
+            timer_remainder: self.timer_remainder,
+            timer_interval: self.timer_interval,
+            last_timer_tick: self.last_timer_tick,
+            sound_timer_total: self.sound_timer_total,
+            audio_pitch: self.audio_pitch,
+            audio_pattern: self.audio_pattern,
+            rpl_flags: self.rpl_flags,
+            keypad: self.keypad,
+            rom_size: self.rom_size,
+            rom_start: self.rom_start,
+            applied_patches: self.applied_patches.clone(),
+            labels: self.labels.clone(),
+            rom_checksum: self.rom_checksum,
+            draw_flag: self.draw_flag,
+            display_changed: self.display_changed,
+            wait_for_release: self.wait_for_release,
+            wait_key: self.wait_key,
+            last_instruction: self.last_instruction,
+            fetch_pc: self.fetch_pc,
+            collision_count: self.collision_count,
+            frames_rendered: self.frames_rendered,
+            pause_on_frame: None,
+            pause_on_cycle: None,
+            draws_this_frame: self.draws_this_frame,
+            display_lag_frames: self.display_lag_frames,
+            input_lag_frames: self.input_lag_frames,
+            pending_draw: self.pending_draw,
+            display_lag: self.display_lag.clone(),
+            latest_keypad: self.latest_keypad,
+            input_script: None,
+            input_script_pos: 0,
+            input_recording: None,
+            input_lag: self.input_lag.clone(),
+            cycles: self.cycles,
+            total_cycles: self.total_cycles,
+            target_ips: self.target_ips,
+            recent_pcs: self.recent_pcs,
+            spin_wait_count: self.spin_wait_count,
+            nmi_count: self.nmi_count,
+            instruction_histogram: self.instruction_histogram,
+            opcodes_executed: self.opcodes_executed.clone(),
+            call_depth_histogram: self.call_depth_histogram,
+            max_observed_sp: self.max_observed_sp,
+            rng: self.rng.clone(),
+            frame_start: self.frame_start,
+            sleep_fn: self.sleep_fn,
+            fx29_override: self.fx29_override,
+            fx30_override: self.fx30_override,
+            extensions: HashMap::new(),
+            quirks,
+            platform: self.platform,
+
+            register_trace: VecDeque::new(),
+            register_trace_cap: 0,
+            register_hook: None,
+            watchpoints: Vec::new(),
+            register_watch_hit: None,
+            register_aliases: self.register_aliases,
+            error_recovery: None,
+            draw_hook: None,
+            trace_log: VecDeque::new(),
+            trace_log_cap: 0,
+            profiling: false,
+            timeline: ExecutionTimeline::default(),
+            profile: None,
+            last_cycle_at: None,
+            cycle_deltas: VecDeque::new(),
+            break_on_unknown: false,
+            wait_for_initial_ret: self.wait_for_initial_ret,
+            last_unknown_opcode: self.last_unknown_opcode,
+            breakpoints: BTreeSet::new(),
+            skip_breakpoint: false,
+            running: true,
+            fast_forward_mode: false,
+
+            debug: false,
+            debug_overlay_enabled: self.debug_overlay_enabled,
+            audio_visualization: self.audio_visualization,
+            visualized_pattern: self.visualized_pattern,
+            display_rows: self.display_rows,
+            paused: false
+        }
+    }
+
+    // Same starting point with a different set of quirks, for finding out which
+    // quirk a ROM depends on. Like execute_all_from_pc, extensions and hooks aren't copied.
+    pub fn clone_with_different_quirks(&self, quirks: Quirks) -> Chip8 {
+        let mut chip8 = self.detached_copy();
+        chip8.set_quirks(quirks);
+        chip8
+    }
+
+    pub fn compare_state(&self, other: &Chip8) -> StateComparison {
+        StateComparison {
+            registers_match: self.v == other.v && self.i == other.i,
+            display_match: self.display == other.display && self.plane2 == other.plane2
+                && self.hires == other.hires && self.hires_display == other.hires_display,
+            pc_match: self.pc == other.pc
+        }
+    }
+
+    fn reference_state(&self) -> ReferenceState {
+        ReferenceState {
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp as u8,
+            memory: self.memory.clone(),
+            display: self.display
+        }
+    }
+
+    // Runs rom for up to cycles instructions here and through other_impl on a second copy,
+    // recording every instruction after which the two disagree. The copy is brought back in
+    // line after each divergence so one bug doesn't get reported on every following cycle.
+    // A ROM that can't be loaded never runs on either side, so there's nothing to report.
+    pub fn compare_to_reference(&self, other_impl: fn(&Instruction, &mut Chip8) -> u8, rom: &[u8], cycles: u64) -> Vec<DivergencePoint> {
+        let mut ours = self.detached_copy();
+        ours.hard_reset();
+        if let Err(err) = ours.load_rom_bytes(rom) {
+            log::warn!("Couldn't load the ROM to compare against the reference: {}", err);
+            return Vec::new();
+        }
+        let mut theirs = ours.detached_copy();
+
+        let mut divergences: Vec<DivergencePoint> = Vec::new();
+        for cycle in 0..cycles {
+            if ours.pc as usize + 1 >= ours.memory.len() {
+                break;
+            }
+            let pc = ours.pc as usize;
+            let instruction = (ours.memory[pc] as u16) << 8 | ours.memory[pc + 1] as u16;
+
+            let result = ours.cycle();
+            let Ok(inst) = theirs.fetch() else {
+                break;
+            };
+            other_impl(&inst, &mut theirs);
+
+            let (self_state, other_state) = (ours.reference_state(), theirs.reference_state());
+            if self_state != other_state {
+                divergences.push(DivergencePoint { cycle, instruction, self_state, other_state });
+                theirs = ours.detached_copy();
+            }
+
+            if !matches!(result, Ok(SUCCESSFUL_EXECUTION)) {
+                break;
             }
         }
 
-        print!(" I: 0x{:X}\r\n\n", self.i)
+        divergences
     }
 
-    pub fn load_file_to_memory(&mut self, path: String, start_location: usize) -> Vec<String> {
-        let mut i: bool = false;
-        let mut offset: usize = 0;
-        let mut files: Vec<String> = Vec::new();
-    
-        // Open file
-        let file = File::open(path).unwrap();
-        let reader = io::BufReader::new(file);
-    
-        for line_result in reader.lines() {
-            let line = line_result.unwrap();
-            let trimmed = line.trim();
-            if i {
-                files.push(trimmed.to_owned()); // Add file names to file vector
-                i = false;
-                continue;
-            } else {
-                i = true;
+    // Pixels that differ from a reference frame packed 8 to a byte, most significant bit on the left
+    pub fn quirk_score(&self, reference: &[u8; 256]) -> u32 {
+        let mut mismatches = 0;
+        for (y, row) in self.display.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let expected = (reference[(y * DISPLAY_WIDTH + x) / 8] >> (7 - x % 8)) & 1 == 1;
+                if pixel != expected {
+                    mismatches += 1;
+                }
             }
-    
-            // Add to chip8 memory
-            for ch in trimmed.chars() {
-                let ascii_value = ch as u8;
-                self.memory[start_location + offset as usize] = ascii_value;
-                offset += 1;
+        }
+        mismatches
+    }
+
+    // Runs the ROM under every combination of the 5 boolean quirks and returns the one whose
+    // display ends up closest to reference_frame. Ties go to the combination closest to CHIP-8.
+    pub fn auto_quirk_tune(rom: &[u8], reference_frame: &[u8; 256], cycles: u64) -> Result<Quirks, Chip8Error> {
+        let mut best = (u32::MAX, Quirks::chip8());
+
+        for combination in 0..32u8 {
+            let flipped = |bit: u8| combination & (1 << bit) != 0;
+            let quirks = Quirks::builder()
+                .load_store(!flipped(0))
+                .shift(flipped(1))
+                .jump(flipped(2))
+                .vf_reset(flipped(3))
+                .clip(flipped(4))
+                .build();
+
+            // Fixed seed so every combination sees the same random numbers
+            let mut chip8 = Chip8::new_with_seed(quirks, [0; 32]);
+            chip8.load_rom_bytes(rom)?;
+            // Errors just end the run early, the display so far still gets scored
+            let _ = chip8.fast_forward(cycles);
+
+            let score = chip8.quirk_score(reference_frame);
+            if score < best.0 {
+                best = (score, quirks);
             }
-            self.memory[start_location + offset as usize] = 0x06; // ACK byte at end of each word
-            offset += 1;
         }
-    
-        files
+
+        Ok(best.1)
     }
 
-    pub fn load_rom(&mut self, filename: &str) -> std::io::Result<()> {
-        // Open the file and auto-return if it fails
-        let data = std::fs::read(filename)?;
+    pub fn emulate_original_display_wait(&mut self, enabled: bool) {
+        self.quirks.display_wait = enabled;
+    }
+
+    pub fn set_sleep_fn(&mut self, sleep_fn: fn(Duration)) {
+        self.sleep_fn = sleep_fn;
+    }
+
+    // Holds back draw_flag until the given number of timer ticks after the draw
+    pub fn emulate_display_lag(&mut self, frames: u8) {
+        self.display_lag_frames = frames;
+        self.display_lag = VecDeque::from(vec![false; frames.saturating_sub(1) as usize]);
+    }
 
-        // Copy each byte into memory starting at 0x200
-        for (i, byte) in data.iter().enumerate() {
-            self.memory[ROM_START + i] = *byte;
+    // Keys passed to set_keypad only reach the keypad after the given number of timer ticks
+    pub fn keypad_read_lag(&mut self, frames: u8) {
+        self.input_lag_frames = frames;
+        self.input_lag = VecDeque::from(vec![self.keypad; frames.saturating_sub(1) as usize]);
+    }
+
+    pub fn set_keypad(&mut self, keys: [bool; 16]) {
+        if let Some(recording) = self.input_recording.as_mut() {
+            recording.push(self.cycles, keys);
         }
+        if self.input_lag_frames == 0 {
+            self.keypad = keys;
+        } else {
+            self.latest_keypad = keys;
+        }
+    }
 
-        Ok(())
+    // Plays script back from cycle 0 after the last reset, in place of whatever set_keypad is given
+    pub fn set_input_script(&mut self, script: InputScript) {
+        self.input_script = Some(script);
+        self.input_script_pos = 0;
     }
 
-    pub fn fetch(&mut self) -> Instruction {
-        let raw = (self.memory[self.pc as usize] as u16) << 8 |  self.memory[(self.pc + 1) as usize] as u16;
-        self.pc += 2;
-        Instruction::new(raw)
+    pub fn clear_input_script(&mut self) {
+        self.input_script = None;
     }
 
-    pub fn execute(&mut self, inst: Instruction) -> std::io::Result<u8> {
-        // Execute
-        match inst.nibble {
-            0x0 => {
-                match inst.nn {
-                    0xE0 => {
-                        // Clear display
-                        self.display = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
-                    },
-                    0xEE => {
-                        // Return from main (close ROM)
-                        if self.sp == 0 {
-                            return Ok(EXIT_ROM);
-                        }
-                        // return from sub function
-                        self.sp -= 1;
-                        self.pc = self.stack[self.sp];
-                    },
-                    _ => { println!("Unknown opcode: {:04X}", inst.instruction); }
-                }
-            }
-            0x1 => {
-                // Jump: PC = NNN
-                self.pc = inst.nnn;
-            }
-            0x2 => {
-                // JAL: STACK[SP] = PC AND PC = NNN
-                self.stack[self.sp] = self.pc;
-                self.sp += 1;
-                self.pc = inst.nnn;
-            }
-            0x3 => {
-                // Skip 1 instruction if VX == NN
-                if self.v[inst.x] == inst.nn {
-                    self.pc += 2;
-                }
+    pub fn has_input_script(&self) -> bool {
+        self.input_script.is_some()
+    }
+
+    // Records every keypad change made through set_keypad until take_input_recording
+    pub fn start_input_recording(&mut self) {
+        self.input_recording = Some(InputScript::new());
+    }
+
+    pub fn take_input_recording(&mut self) -> Option<InputScript> {
+        self.input_recording.take()
+    }
+
+    fn apply_input_script(&mut self) {
+        let Some(script) = self.input_script.as_ref() else {
+            return;
+        };
+        let mut keys = None;
+        while let Some(event) = script.events().get(self.input_script_pos).filter(|event| event.at_cycle <= self.cycles) {
+            keys = Some(event.state);
+            self.input_script_pos += 1;
+        }
+        if let Some(keys) = keys {
+            self.set_keypad(keys);
+        }
+    }
+
+    // Moves the lag queues along by one frame
+    fn advance_lag(&mut self) {
+        if self.display_lag_frames > 0 {
+            self.display_lag.push_back(self.pending_draw);
+            self.pending_draw = false;
+            if self.display_lag.pop_front() == Some(true) {
+                self.draw_flag = true;
             }
-            0x4 => {
-                // Skip 1 instruction if VX != NN
-                if self.v[inst.x] != inst.nn {
-                    self.pc += 2;
-                }
+        }
+
+        if self.input_lag_frames > 0 {
+            self.input_lag.push_back(self.latest_keypad);
+            if let Some(keys) = self.input_lag.pop_front() {
+                self.keypad = keys;
             }
-            0x5 => {
-                match inst.n {
+        }
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    // Only the quirks change, the platform stays as it is
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // Switches to the platform's usual quirks as well
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.quirks = platform.quirks();
+        self.platform = platform;
+    }
+
+    // Read-only CPU state for debuggers and tools, v and the timers are already pub
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Moves execution somewhere else, for debuggers
+    pub fn set_pc(&mut self, addr: u16) {
+        self.pc = addr;
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    // Register FX0A is waiting to fill, only meaningful while wait_for_release() is true
+    pub fn wait_key(&self) -> usize {
+        self.wait_key
+    }
+
+    pub fn wait_for_release(&self) -> bool {
+        self.wait_for_release
+    }
+
+    pub fn set_i_overflow_behavior(&mut self, behavior: IOverflowBehavior) {
+        self.quirks.i_overflow = behavior;
+    }
+
+    // 0 lifts the limit again
+    pub fn configure_vblank_draw_limit(&mut self, max_draws_per_frame: u8) {
+        self.quirks.max_draws_per_vblank = max_draws_per_frame;
+    }
+
+    // Rate the delay and sound timers count down at in tick_timers_with_debt, 0 is taken as 1Hz
+    pub fn set_timer_hz(&mut self, hz: u64) {
+        self.timer_interval = Duration::from_nanos(1_000_000_000 / hz.max(1));
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.draws_this_frame = 0;
+        self.advance_lag();
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer_total += 1;
+            self.sound_timer -= 1;
+        }
+    }
+
+    pub fn tick_timers_with_debt(&mut self, elapsed: Duration) {
+        let start = self.profiling.then(Instant::now);
+
+        // Work out how many timer ticks (60Hz by default) fit in the time since the last
+        // call, carrying the leftover fraction over to the next call
+        self.timer_remainder += elapsed;
+        self.last_timer_tick = Instant::now();
+        let ticks = (self.timer_remainder.as_nanos() / self.timer_interval.as_nanos()) as u32;
+        self.timer_remainder -= self.timer_interval * ticks;
+
+        // Ticks a single-tick-per-call approach would have dropped
+        self.timer_debt = ticks.saturating_sub(1).min(u8::MAX as u32) as u8;
+
+        let ticks = ticks.min(u8::MAX as u32) as u8;
+        if ticks > 0 {
+            self.draws_this_frame = 0;
+        }
+        if self.display_lag_frames > 0 || self.input_lag_frames > 0 {
+            for _ in 0..ticks {
+                self.advance_lag();
+            }
+        }
+        let sound_ticks = ticks.min(self.sound_timer);
+        self.delay_timer -= ticks.min(self.delay_timer);
+        self.sound_timer -= sound_ticks;
+        self.sound_timer_total += sound_ticks as u64;
+
+        if let Some(start) = start {
+            self.timeline.timer_us_total += start.elapsed().as_micros() as u64;
+        }
+    }
+
+    // Time left until the next timer tick boundary, None when neither timer is counting down
+    pub fn time_until_timer_expires(&self) -> Option<Duration> {
+        self.time_until_timer_expires_at(Instant::now())
+    }
+
+    pub fn time_until_timer_expires_at(&self, now: Instant) -> Option<Duration> {
+        if self.delay_timer == 0 && self.sound_timer == 0 {
+            return None;
+        }
+
+        let since_boundary = self.timer_remainder + now.saturating_duration_since(self.last_timer_tick);
+        let into_tick = since_boundary.as_nanos() % self.timer_interval.as_nanos();
+        Some(self.timer_interval - Duration::from_nanos(into_tick as u64))
+    }
+
+    pub fn timer_debt(&self) -> u8 {
+        self.timer_debt
+    }
+
+    // Number of 60Hz ticks the sound timer has been active for since reset
+    pub fn sound_timer_total(&self) -> u64 {
+        self.sound_timer_total
+    }
+
+    pub fn sound_total_seconds(&self) -> f64 {
+        self.sound_timer_total as f64 / 60.0
+    }
+
+    pub fn audio_pitch(&self) -> u8 {
+        self.audio_pitch
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn set_audio_pattern(&mut self, pattern: [u8; 16]) {
+        self.audio_pattern = pattern;
+    }
+
+    // Writes duration_ms of the audio pattern looping at the current pitch to a 16 bit mono WAV
+    #[cfg(feature = "wav")]
+    pub fn emit_wav_from_audio_pattern(&self, path: &str, duration_ms: u32) -> Result<(), Chip8Error> {
+        const SAMPLE_RATE: u32 = 44100;
+        const AMPLITUDE: i16 = i16::MAX / 2;
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int
+        };
+        let to_chip8_error = |err: hound::Error| match err {
+            hound::Error::IoError(err) => Chip8Error::IoError(err),
+            err => Chip8Error::IoError(io::Error::other(err))
+        };
+
+        // Pattern bits play back at 4000 * 2^((pitch - 64) / 48) per second
+        let bit_rate = 4000.0 * 2f64.powf((self.audio_pitch as f64 - 64.0) / 48.0);
+        let sample_count = SAMPLE_RATE as u64 * duration_ms as u64 / 1000;
+
+        let mut writer = hound::WavWriter::create(path, spec).map_err(to_chip8_error)?;
+        for sample in 0..sample_count {
+            let bit = (sample as f64 / SAMPLE_RATE as f64 * bit_rate) as usize % 128;
+            let on = (self.audio_pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+            writer.write_sample(if on { AMPLITUDE } else { -AMPLITUDE }).map_err(to_chip8_error)?;
+        }
+        writer.finalize().map_err(to_chip8_error)
+    }
+
+    // Reserves the bottom OLED page for a waveform of the audio pattern. The Chip8
+    // display is limited to rows 0-23 so it never draws underneath the waveform.
+    pub fn enable_audio_visualization(&mut self, screen: &mut DisplayInterface) -> Result<(), DisplayError> {
+        self.audio_visualization = true;
+        self.display_rows = AUDIO_VISUALIZATION_ROWS;
+        self.visualized_pattern = None;
+        screen.clear_region(0, AUDIO_VISUALIZATION_PAGE, 128, 1)
+    }
+
+    pub fn disable_audio_visualization(&mut self) {
+        self.audio_visualization = false;
+        self.display_rows = DISPLAY_HEIGHT;
+        self.visualized_pattern = None;
+    }
+
+    // Call on every timer tick. The waveform is redrawn for as long as the sound is on,
+    // since rendering the Chip8 display writes over the bottom page too.
+    pub fn render_audio_visualization(&mut self, screen: &mut DisplayInterface) -> Result<(), DisplayError> {
+        if !self.audio_visualization {
+            return Ok(());
+        }
+
+        if self.sound_timer == 0 {
+            // Sound stopped, clear the waveform
+            if self.visualized_pattern.take().is_some() {
+                screen.clear_region(0, AUDIO_VISUALIZATION_PAGE, 128, 1)?;
+            }
+            return Ok(());
+        }
+
+        screen.send_cmd(0xB0 + AUDIO_VISUALIZATION_PAGE);
+        screen.send_cmd(0x00);
+        screen.send_cmd(0x10);
+        screen.send_data(&self.audio_waveform());
+        self.visualized_pattern = Some(self.audio_pattern);
+        Ok(())
+    }
+
+    // The bottom page as render_audio_visualization draws it. Each pattern byte is 8 columns
+    // wide, set bits are full height bars and cleared bits leave just the baseline.
+    pub fn audio_waveform(&self) -> [u8; 128] {
+        let mut waveform = [0; 128];
+        for (col, byte) in waveform.iter_mut().enumerate() {
+            let bit = 7 - col % 8;
+            *byte = if (self.audio_pattern[col / 8] >> bit) & 1 == 1 { 0xFF } else { 0x80 };
+        }
+        waveform
+    }
+
+    // Replaces the small hex digits FX29 points at. hard_reset() puts the default font back, so
+    // call this after any hard_reset (loading a ROM leaves it alone).
+    pub fn load_fontset(&mut self, font: &[u8; 80]) {
+        self.memory[FONTSET_START..FONTSET_START + font.len()].copy_from_slice(font);
+    }
+
+    pub const fn fontset_start() -> usize {
+        FONTSET_START
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; RPL_FLAG_COUNT] {
+        &self.rpl_flags
+    }
+
+    // Keeps FX75 high scores and settings across power cycles
+    pub fn save_rpl(&self, path: &str) -> io::Result<()> {
+        std::fs::write(path, self.rpl_flags)
+    }
+
+    pub fn load_rpl(&mut self, path: &str) -> io::Result<()> {
+        let data = std::fs::read(path)?;
+        self.rpl_flags = data.try_into().map_err(|data: Vec<u8>| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected {} RPL flag bytes, found {}", RPL_FLAG_COUNT, data.len())
+        ))?;
+        Ok(())
+    }
+
+    // For fonts stored somewhere other than the built-in font sets
+    pub fn set_fx29_override(&mut self, f: fn(digit: u8) -> u16) {
+        self.fx29_override = Some(f);
+    }
+
+    pub fn set_fx30_override(&mut self, f: fn(digit: u8) -> u16) {
+        self.fx30_override = Some(f);
+    }
+
+    pub fn clear_font_overrides(&mut self) {
+        self.fx29_override = None;
+        self.fx30_override = None;
+    }
+
+    pub fn register_opcode_extension(&mut self, opcode: u16, handler: Box<dyn OpcodeHandler>) {
+        self.extensions.insert(opcode, handler);
+    }
+
+    fn wait_for_vblank(&self) {
+        // Sleep until the next 60Hz boundary measured from frame_start
+        let elapsed = self.frame_start.elapsed().as_micros() as u64;
+        let next_vblank = (elapsed / VBLANK_INTERVAL_US + 1) * VBLANK_INTERVAL_US;
+        (self.sleep_fn)(Duration::from_micros(next_vblank - elapsed));
+    }
+
+    // Waits for the OLED to start a new scan so a frame sent straight after doesn't tear
+    pub fn await_vsync(&self, display: &mut DisplayInterface) {
+        if !self.fast_forward_mode {
+            display.await_vsync();
+        }
+    }
+
+    pub fn get_display(&self) -> &[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT] {
+        &self.display
+    }
+
+    // Whether SUPER-CHIP 00FF has switched to the 128x64 display
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn get_hires_display(&self) -> &[[bool; HIRES_WIDTH]; HIRES_HEIGHT] {
+        &self.hires_display
+    }
+
+    // Moves the active display dx columns right (left when negative) and dy rows down, pixels
+    // pushed off the edge are lost and the vacated ones are cleared. Only the selected planes move.
+    fn scroll_display(&mut self, dx: isize, dy: usize) {
+        if self.hires {
+            Self::scroll_buffer(&mut self.hires_display, dx, dy);
+        } else {
+            for plane in 0..2 {
+                if self.plane_mask & (1 << plane) != 0 {
+                    Self::scroll_buffer(self.plane_mut(plane), dx, dy);
+                }
+            }
+        }
+        self.recompute_display_hash();
+        self.display_changed = true;
+        self.draw_flag = true;
+    }
+
+    fn scroll_buffer<const W: usize, const H: usize>(buffer: &mut [[bool; W]; H], dx: isize, dy: usize) {
+        let source = *buffer;
+        for (y, row) in buffer.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let from_x = x as isize - dx;
+                *pixel = y >= dy && (0..W as isize).contains(&from_x) && source[y - dy][from_x as usize];
+            }
+        }
+    }
+
+    // XO-CHIP bitplanes, 0 is the regular display
+    pub fn get_plane(&self, plane: usize) -> &[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT] {
+        if plane == 0 { &self.display } else { &self.plane2 }
+    }
+
+    fn plane_mut(&mut self, plane: usize) -> &mut [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT] {
+        if plane == 0 { &mut self.display } else { &mut self.plane2 }
+    }
+
+    // Bit 0 selects plane 1 (the regular display), bit 1 selects plane 2
+    pub fn set_plane_write_mask(&mut self, mask: u8) -> Result<(), Chip8Error> {
+        if mask > 3 {
+            return Err(Chip8Error::InvalidPlaneMask(mask));
+        }
+        self.plane_mask = mask;
+        Ok(())
+    }
+
+    pub fn plane_write_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    // Number of pixels that differ from a previously captured frame
+    pub fn display_diff(&self, prev: &[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT]) -> u32 {
+        self.display.iter().flatten()
+            .zip(prev.iter().flatten())
+            .filter(|(current, previous)| current != previous)
+            .count() as u32
+    }
+
+    pub fn get_display_pixel(&self, x: usize, y: usize) -> Result<bool, Chip8Error> {
+        if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT {
+            return Err(Chip8Error::MemoryOutOfBounds(y * DISPLAY_WIDTH + x));
+        }
+        Ok(self.display[y][x])
+    }
+
+    pub fn get_display_row(&self, row: usize) -> Result<[bool; DISPLAY_WIDTH], Chip8Error> {
+        if row >= DISPLAY_HEIGHT {
+            return Err(Chip8Error::MemoryOutOfBounds(row * DISPLAY_WIDTH));
+        }
+        Ok(self.display[row])
+    }
+
+    pub fn set_display_pixel(&mut self, x: usize, y: usize, value: bool) -> Result<(), Chip8Error> {
+        if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT {
+            return Err(Chip8Error::MemoryOutOfBounds(y * DISPLAY_WIDTH + x));
+        }
+        if self.display[y][x] != value {
+            self.display_changed = true;
+            self.display_hash ^= Self::pixel_hash(0, x, y);
+        }
+        self.display[y][x] = value;
+        self.draw_flag = true;
+        Ok(())
+    }
+
+    // XOR of a key per lit pixel, so toggling a pixel twice puts the hash back where it was
+    pub fn display_buffer_hash(&self) -> u64 {
+        self.display_hash
+    }
+
+    fn pixel_hash(plane: usize, x: usize, y: usize) -> u64 {
+        Self::mix_pixel_index(((plane * DISPLAY_HEIGHT + y) * DISPLAY_WIDTH + x + 1) as u64)
+    }
+
+    // Hi-res pixels are numbered after both lo-res planes
+    fn hires_pixel_hash(x: usize, y: usize) -> u64 {
+        Self::mix_pixel_index((2 * DISPLAY_HEIGHT * DISPLAY_WIDTH + y * HIRES_WIDTH + x + 1) as u64)
+    }
+
+    fn mix_pixel_index(mut z: u64) -> u64 {
+        // splitmix64 finalizer over the pixel's position
+        z = z.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn recompute_display_hash(&mut self) {
+        let mut hash = DISPLAY_HASH_SEED;
+        for (plane, pixels) in [&self.display, &self.plane2].into_iter().enumerate() {
+            for (y, row) in pixels.iter().enumerate() {
+                for (x, &pixel) in row.iter().enumerate() {
+                    if pixel {
+                        hash ^= Self::pixel_hash(plane, x, y);
+                    }
+                }
+            }
+        }
+        for (y, row) in self.hires_display.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    hash ^= Self::hires_pixel_hash(x, y);
+                }
+            }
+        }
+        self.display_hash = hash;
+    }
+
+    pub fn last_executed_instruction(&self) -> Option<Instruction> {
+        self.last_instruction.map(Instruction::new)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn display_as_sixel(&self) -> String {
+        // DCS header, 1:1 aspect ratio, 64x32 raster, with a black/white palette
+        let mut out = String::from("\x1bPq\"1;1;64;32#0;2;0;0;0#1;2;100;100;100");
+
+        // Each sixel band covers 6 pixel rows, the last band is padded with off pixels
+        for band in 0..DISPLAY_HEIGHT.div_ceil(6) {
+            out.push_str("#1");
+            for col in 0..DISPLAY_WIDTH {
+                let mut bits: u8 = 0;
+                for offset in 0..6 {
+                    let row = band * 6 + offset;
+                    if row < DISPLAY_HEIGHT && self.display[row][col] {
+                        bits |= 1 << offset;
+                    }
+                }
+                out.push((bits + 63) as char);
+            }
+            out.push('-');
+        }
+
+        out.push_str("\x1b\\");
+        out
+    }
+
+    #[cfg(feature = "std")]
+    pub fn display_as_ascii(&self) -> String {
+        let mut out = String::with_capacity(DISPLAY_HEIGHT * (DISPLAY_WIDTH * 3 + 1));
+        for row in self.display.iter() {
+            for &pixel in row.iter() {
+                out.push(if pixel { '█' } else { ' ' });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Two pixel rows per line with half blocks, 16 lines of 64 characters. Small enough to
+    // send over a serial console when there's no screen attached.
+    #[cfg(feature = "std")]
+    pub fn display_as_string(&self) -> String {
+        let mut out = String::with_capacity(DISPLAY_HEIGHT / 2 * (DISPLAY_WIDTH * 3 + 1));
+        for rows in self.display.chunks(2) {
+            for (&top, &bottom) in rows[0].iter().zip(rows[1].iter()) {
+                out.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' '
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Draws the display into an RGBA buffer, centred with each pixel scale x scale in size.
+    // Anything that doesn't fit is cut off.
+    pub fn render_to_framebuffer(&self, fb: &mut [u8], width: usize, height: usize, scale: usize) {
+        const ON: [u8; 4] = [255, 255, 255, 255];
+        const OFF: [u8; 4] = [0, 0, 0, 255];
+
+        let x_offset = width.saturating_sub(DISPLAY_WIDTH * scale) / 2;
+        let y_offset = height.saturating_sub(DISPLAY_HEIGHT * scale) / 2;
+
+        for (row, pixels) in self.display.iter().enumerate() {
+            for (col, &pixel) in pixels.iter().enumerate() {
+                let colour = if pixel { ON } else { OFF };
+                for dy in 0..scale {
+                    let y = y_offset + row * scale + dy;
+                    if y >= height {
+                        break;
+                    }
+                    for dx in 0..scale {
+                        let x = x_offset + col * scale + dx;
+                        if x >= width {
+                            break;
+                        }
+                        let index = (y * width + x) * 4;
+                        if let Some(target) = fb.get_mut(index..index + 4) {
+                            target.copy_from_slice(&colour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn open_framebuffer(scale: usize) -> Result<FramebufferRenderer, io::Error> {
+        FramebufferRenderer::open(scale)
+    }
+
+    // Unlike draw_flag, only set when a pixel actually changed state
+    pub fn display_changed_since_last_render(&self) -> bool {
+        self.display_changed
+    }
+
+    // Sends the active display (hi-res or not) to the screen and clears the draw flag
+    pub fn render_to(&mut self, screen: &mut dyn ChipDisplay) {
+        if self.hires {
+            screen.display_hires_array(&self.hires_display);
+        } else if self.platform == Platform::XoChip {
+            screen.display_planes(&self.display, &self.plane2);
+        } else {
+            screen.display_2d_array(self.display);
+        }
+        self.mark_rendered();
+    }
+
+    pub fn mark_rendered(&mut self) {
+        self.draw_flag = false;
+        self.display_changed = false;
+    }
+
+    // Draw instructions that made it to the display since reset, dropped draws don't count
+    pub fn frames_rendered(&self) -> u64 {
+        self.frames_rendered
+    }
+
+    // Pauses straight after the draw that makes frames_rendered reach n. Like other
+    // pauses, the game loop only stops for it in debug mode. Fires once.
+    pub fn debug_pause_on_frame(&mut self, n: u64) {
+        self.pause_on_frame = Some(n);
+    }
+
+    // Pauses once cycles_since_reset reaches n
+    pub fn debug_pause_on_cycle(&mut self, n: u64) {
+        self.pause_on_cycle = Some(n);
+    }
+
+    // Number of draw instructions that collided with lit pixels since reset
+    pub fn pixel_collision_count(&self) -> u64 {
+        self.collision_count
+    }
+
+    // Draws a status line over the bottom page of the OLED. Chip8 rows 28-31 share that
+    // page, so they are masked off in the draw instruction while the overlay is enabled.
+    pub fn render_debug_overlay(&self, screen: &mut DisplayInterface) -> Result<(), DisplayError> {
+        let status = format!("PC:{:04X} VF:{:02X} DT:{:02X}", self.pc, self.v[0xF], self.delay_timer);
+        screen.clear_region(0, DEBUG_OVERLAY_PAGE, 128, 1)?;
+        screen.draw_text(DEBUG_OVERLAY_PAGE, 0, &status)
+    }
+
+    // Copies values into V[start..=end], values has to cover the whole range
+    pub fn set_v_register_range(&mut self, start: usize, end: usize, values: &[u8]) -> Result<(), Chip8Error> {
+        if end >= self.v.len() || start > end || values.len() != end - start + 1 {
+            return Err(Chip8Error::InvalidRegister { start, end });
+        }
+        self.v[start..=end].copy_from_slice(values);
+        Ok(())
+    }
+
+    pub fn get_v_register_range(&self, start: usize, end: usize) -> Result<Vec<u8>, Chip8Error> {
+        if end >= self.v.len() || start > end {
+            return Err(Chip8Error::InvalidRegister { start, end });
+        }
+        Ok(self.v[start..=end].to_vec())
+    }
+
+    pub fn annotate_register(&mut self, reg: u8, name: &'static str, purpose: &'static str) {
+        let reg = reg & 0xF;
+        self.register_aliases[reg as usize] = Some(RegisterAlias { reg, name, purpose });
+    }
+
+    pub fn get_register_alias(&self, reg: u8) -> Option<&RegisterAlias> {
+        self.register_aliases.get(reg as usize)?.as_ref()
+    }
+
+    // Alias names where set, V0-VF otherwise
+    pub fn get_register_names(&self) -> [&'static str; 16] {
+        std::array::from_fn(|reg| self.register_aliases[reg].map_or(REGISTER_NAMES[reg], |alias| alias.name))
+    }
+
+    // Registers, then the display underneath in half blocks
+    pub fn debug_print(&self) {
+        println!("PC: 0x{:X}", self.pc());
+        let mut line: u8 = 0;
+        for register in 0..16 {
+            match self.register_aliases[register] {
+                Some(alias) => print!("v{:X} ({}): 0x{:X}  \t", register, alias.name, self.v[register]),
+                None => print!("v{:X}: 0x{:X}  \t", register, self.v[register])
+            }
+            if line >= 4 {
+                print!("\r\n");
+                line = 0;
+            } else {
+                line += 1;
+            }
+        }
+
+        print!(" I: 0x{:X}  SP: {}\r\n\n", self.i(), self.sp());
+        #[cfg(feature = "std")]
+        print!("{}", self.display_as_string());
+    }
+
+    // Everything worth seeing while stopped at a breakpoint: the next instruction, registers, stack
+    // and the bytes I points at
+    pub fn debug_print_breakpoint(&self) {
+        print!("{}", self.disassemble_range(self.pc, self.pc.wrapping_add(1)));
+        print!("{}", self.dump_registers());
+        println!("Stack: {:03X?}", &self.stack[..self.sp]);
+        println!("{}", self.dump_memory(self.i, self.i.saturating_add(16)));
+    }
+
+    // xxd style hex dump of start..end, 16 bytes a line with printable ASCII on the right.
+    // Both ends get clamped to memory. Lines holding part of the loaded ROM are marked.
+    pub fn dump_memory(&self, start: u16, end: u16) -> String {
+        const BYTES_PER_LINE: usize = 16;
+
+        let end = (end as usize).min(self.memory.len());
+        let start = (start as usize).min(end);
+        let rom = self.rom_range();
+        let mut out = String::new();
+        for (line, bytes) in self.memory[start..end].chunks(BYTES_PER_LINE).enumerate() {
+            let addr = start + line * BYTES_PER_LINE;
+            out.push_str(&format!("0x{:04X}:", addr));
+            for byte in bytes {
+                out.push_str(&format!(" {:02X}", byte));
+            }
+            out.push_str(&"   ".repeat(BYTES_PER_LINE - bytes.len()));
+            out.push_str("  ");
+            for &byte in bytes {
+                out.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+            }
+            if addr < rom.end && rom.start < addr + bytes.len() {
+                out.push_str(&" ".repeat(BYTES_PER_LINE - bytes.len()));
+                out.push_str("  ROM");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn dump_registers(&self) -> String {
+        let mut out = String::new();
+        for (row, values) in self.v.chunks(4).enumerate() {
+            let cells: Vec<String> = values.iter().enumerate()
+                .map(|(col, value)| format!("V{:X}: {:02X}", row * 4 + col, value))
+                .collect();
+            out.push_str(&cells.join("  "));
+            out.push('\n');
+        }
+        out.push_str(&format!("I: {:04X}  PC: {:04X}  SP: {:X}\n", self.i, self.pc, self.sp));
+        out.push_str(&format!("DT: {:02X}  ST: {:02X}  Draw: {}\n", self.delay_timer, self.sound_timer, self.draw_flag));
+        out
+    }
+
+    // ROM list for the menu: alternating display name and file name lines. The names go into
+    // memory at start_location, each ended with an ACK byte, and the file names are returned.
+    pub fn load_file_to_memory(&mut self, path: String, start_location: usize) -> Result<Vec<String>, Chip8Error> {
+        let reader = io::BufReader::new(File::open(&path)?);
+        let mut names: Vec<&str> = Vec::new();
+        let mut files: Vec<String> = Vec::new();
+
+        let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+        if lines.iter().all(|line| line.trim().is_empty()) {
+            return Err(Chip8Error::InvalidRomData(format!("{} is empty", path)));
+        }
+
+        for (index, pair) in lines.chunks(2).enumerate() {
+            let name = pair[0].trim();
+            let file = pair.get(1).map(|line| line.trim()).unwrap_or_default();
+            if file.is_empty() {
+                return Err(Chip8Error::InvalidRomData(format!("{} line {}: no file name for {:?}", path, index * 2 + 2, name)));
+            }
+
+            names.push(name);
+            files.push(file.to_owned());
+        }
+
+        self.load_menu_names(&names, start_location)?;
+        Ok(files)
+    }
+
+    // Game names for the menu ROM, each ended with an ACK byte
+    pub fn load_menu_names(&mut self, names: &[&str], start_location: usize) -> Result<(), Chip8Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        for name in names {
+            if !name.is_ascii() {
+                return Err(Chip8Error::InvalidRomData(format!("{:?} isn't ASCII", name)));
+            }
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(0x06); // ACK byte at end of each word
+        }
+
+        let end = start_location + bytes.len();
+        if end > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds(end - 1));
+        }
+        self.memory[start_location..end].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn load_rom(&mut self, filename: &str) -> Result<(), Chip8Error> {
+        // Open the file and auto-return if it fails
+        let data = std::fs::read(filename)?;
+        self.load_rom_bytes(&data)
+    }
+
+    pub const fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    // Bytes loaded from the load address onwards
+    pub fn rom_size(&self) -> usize {
+        self.rom_size
+    }
+
+    pub fn rom_load_address(&self) -> u16 {
+        self.rom_start as u16
+    }
+
+    fn rom_range(&self) -> std::ops::Range<usize> {
+        self.rom_start..self.rom_start + self.rom_size
+    }
+
+    // Overwrites loaded ROM bytes without touching the file, nothing is applied
+    // unless every address falls inside the ROM
+    pub fn apply_rom_patch(&mut self, patches: &[(u16, u8)]) -> Result<(), Chip8Error> {
+        let rom = self.rom_range();
+        let rom_end = rom.end as u16;
+        if let Some(&(addr, _)) = patches.iter().find(|&&(addr, _)| !rom.contains(&(addr as usize))) {
+            return Err(Chip8Error::PatchOutOfRange { addr, rom_end });
+        }
+
+        for &(addr, byte) in patches {
+            self.memory[addr as usize] = byte;
+            self.applied_patches.push((addr, byte));
+        }
+        Ok(())
+    }
+
+    pub fn applied_patches(&self) -> &[(u16, u8)] {
+        &self.applied_patches
+    }
+
+    // CRC-32 (IEEE) of a memory region, anything past the end of memory is left out
+    pub fn memory_checksum(&self, region: std::ops::Range<usize>) -> u32 {
+        let end = region.end.min(self.memory.len());
+        let start = region.start.min(end);
+        Self::crc32(&self.memory[start..end])
+    }
+
+    pub fn checksum_of_memory_region(&self, start: usize, len: usize) -> u32 {
+        self.memory_checksum(start..start.saturating_add(len))
+    }
+
+    // Bit at a time rather than a lookup table, it only runs when a ROM is loaded or started
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    // Remembers the checksum of the loaded ROM, loading another ROM forgets it
+    pub fn store_checksum(&mut self) {
+        self.rom_checksum = Some(self.memory_checksum(self.rom_range()));
+    }
+
+    // True when no checksum has been stored
+    pub fn verify_checksum(&self) -> bool {
+        self.rom_checksum.is_none_or(|checksum| checksum == self.memory_checksum(self.rom_range()))
+    }
+
+    // Names for ROM addresses, used by the disassembler. They stay put across reset(), hard_reset() clears them.
+    pub fn add_label(&mut self, addr: u16, name: &'static str) {
+        self.labels.insert(addr, name);
+    }
+
+    pub fn rom_entry_map(&self) -> BTreeMap<u16, &'static str> {
+        self.labels.clone()
+    }
+
+    pub fn clear_labels(&mut self) {
+        self.labels.clear();
+    }
+
+    fn register_name(&self, reg: usize) -> String {
+        match self.register_aliases[reg] {
+            Some(alias) => format!("V{:X} ({})", reg, alias.name),
+            None => format!("V{:X}", reg)
+        }
+    }
+
+    // Cowgod style assembly, with labels and register aliases filled in
+    fn disassemble_instruction(&self, inst: &Instruction) -> String {
+        inst.disassemble_with(|reg| self.register_name(reg), |addr| self.labels.get(&addr).copied())
+    }
+
+    // One line per instruction in [start, end), labelled addresses get a "name:" line first
+    pub fn disassemble_range(&self, start: u16, end: u16) -> String {
+        let mut out = String::new();
+        let mut addr = start as usize;
+        while addr < end as usize && addr + 1 < self.memory.len() {
+            let mut inst = Instruction::new((self.memory[addr] as u16) << 8 | self.memory[addr + 1] as u16);
+            if inst.encoding_size(self.platform) == 4 && addr + 3 < self.memory.len() {
+                inst.extra = Some((self.memory[addr + 2] as u16) << 8 | self.memory[addr + 3] as u16);
+            }
+
+            if let Some(name) = self.labels.get(&(addr as u16)) {
+                out.push_str(&format!("{}:\n", name));
+            }
+            out.push_str(&format!("{:04X}: {:04X}  {}\n", addr, inst.instruction, self.disassemble_instruction(&inst)));
+            addr += inst.encoding_size(self.platform) as usize;
+        }
+        out
+    }
+
+    pub fn disassemble_loaded_rom(&self) -> String {
+        let rom = self.rom_range();
+        self.disassemble_range(rom.start as u16, rom.end as u16)
+    }
+
+    pub fn rom_fingerprint(&self) -> RomFingerprint {
+        let rom = &self.memory[self.rom_range()];
+        let analysis = RomAnalyzer::analyze_at(rom, self.rom_start as u16);
+
+        let instructions = analysis.reachable.values().map(|&raw| Instruction::new(raw));
+        let (mut jump_count, mut draw_count) = (0, 0);
+        for inst in instructions {
+            match inst.nibble {
+                0x1 | 0xB => jump_count += 1,
+                0xD => draw_count += 1,
+                _ => {}
+            }
+        }
+
+        RomFingerprint {
+            sha256: Sha256::digest(rom).into(),
+            size: self.rom_size,
+            first_instruction: analysis.reachable.get(&(self.rom_start as u16)).copied().unwrap_or(0),
+            jump_count,
+            draw_count,
+            uses_schip: !analysis.schip_opcodes.is_empty(),
+            uses_xochip: !analysis.xochip_opcodes.is_empty()
+        }
+    }
+
+    pub fn execution_graph(&self) -> HashMap<u16, Vec<u16>> {
+        RomAnalyzer::control_flow_graph_at(&self.memory[self.rom_range()], self.rom_start as u16)
+    }
+
+    // Graphviz version of execution_graph, indirect jumps go to an "indirect" node
+    pub fn execution_graph_as_dot(&self) -> String {
+        let graph = self.execution_graph();
+        let mut addrs: Vec<&u16> = graph.keys().collect();
+        addrs.sort();
+
+        let mut out = String::from("digraph chip8 {\n    node [shape=box, fontname=monospace];\n");
+        for &addr in addrs {
+            let raw = (self.memory[addr as usize] as u16) << 8 | self.memory[addr as usize + 1] as u16;
+            out.push_str(&format!("    \"{:04X}\" [label=\"{:04X}: {}\"];\n", addr, addr, Self::trace_mnemonic(raw)));
+            for &next in graph[&addr].iter() {
+                if next == u16::MAX {
+                    out.push_str(&format!("    \"{:04X}\" -> \"indirect\";\n", addr));
+                } else {
+                    out.push_str(&format!("    \"{:04X}\" -> \"{:04X}\";\n", addr, next));
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    #[cfg(feature = "http")]
+    fn max_rom_size(&self) -> usize {
+        self.memory.len() - ROM_START
+    }
+
+    // For ROMs that are already in memory, e.g. from include_bytes! on targets without a filesystem
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_rom_bytes_at(data, ROM_LOAD_CHIP8)
+    }
+
+    #[deprecated(note = "use `load_rom_bytes` instead")]
+    pub fn load_rom_from_bytes(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_rom_bytes(data)
+    }
+
+    // For interpreters that load somewhere other than 0x200, e.g. ETI-660 at 0x600.
+    // Execution starts at base, now and after every soft reset.
+    pub fn load_rom_bytes_at(&mut self, data: &[u8], base: u16) -> Result<(), Chip8Error> {
+        let offset = base as usize;
+        if offset < ROM_START {
+            return Err(Chip8Error::MemoryOutOfBounds(offset));
+        }
+        Self::validate_rom(data, self.memory.len().saturating_sub(offset))?;
+
+        // Clear what's left of the previous ROM so a smaller one doesn't inherit its tail
+        let previous = self.rom_range();
+        self.memory[previous].fill(0);
+        self.memory[offset..offset + data.len()].copy_from_slice(data);
+        self.rom_size = data.len();
+        self.rom_start = offset;
+        self.pc = offset as u16;
+        self.applied_patches.clear();
+        self.rom_checksum = None;
+
+        Ok(())
+    }
+
+    // Checks done before a ROM touches memory. An odd size is only warned about,
+    // some ROMs are padded out with a single byte.
+    fn validate_rom(data: &[u8], max: usize) -> Result<(), Chip8Error> {
+        if data.is_empty() {
+            return Err(Chip8Error::EmptyRom);
+        }
+        if data.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: data.len(), max });
+        }
+        if !data.len().is_multiple_of(2) {
+            log::warn!("ROM is {} bytes, an odd size for 2 byte instructions", data.len());
+        }
+        Ok(())
+    }
+
+    #[deprecated(note = "use `load_rom_bytes_at` instead")]
+    pub fn load_rom_with_offset(&mut self, data: &[u8], offset: u16) -> Result<(), Chip8Error> {
+        self.load_rom_bytes_at(data, offset)
+    }
+
+    // Picks quirks from the opcodes the ROM uses and loads it, the recommended way of
+    // loading a ROM of unknown origin. XO-CHIP ROMs get the full 64KB of memory.
+    pub fn from_schip_rom(data: &[u8]) -> Result<(Chip8, Quirks), Chip8Error> {
+        let analysis = RomAnalyzer::analyze(data);
+
+        let (quirks, platform, memory_size) = match analysis.format() {
+            RomFormat::XoChip => {
+                log::info!("XO-CHIP ROM detected from opcodes {:04X?}", analysis.xochip_opcodes);
+                (Quirks::xochip(), Platform::XoChip, 65536)
+            }
+            RomFormat::SuperChip => {
+                log::info!("SCHIP ROM detected from opcodes {:04X?}", analysis.schip_opcodes);
+                (Quirks::superchip11(), Platform::SuperChip, DEFAULT_MEMORY_SIZE)
+            }
+            RomFormat::Chip8 => {
+                log::info!("No SCHIP or XO-CHIP opcodes found, using CHIP-8 quirks");
+                (Quirks::chip8(), Platform::Chip8, DEFAULT_MEMORY_SIZE)
+            }
+        };
+
+        let mut chip8 = Chip8Builder::new().quirks(quirks).platform(platform).memory_size(memory_size).build()?;
+        chip8.load_rom_bytes(data)?;
+        Ok((chip8, quirks))
+    }
+
+    // Static analysis of the ROM plus a run of up to cycles instructions with the suggested quirks
+    pub fn generate_test_report(rom: &[u8], cycles: u64) -> Result<TestReport, Chip8Error> {
+        let analysis = RomAnalyzer::analyze(rom);
+        let detected_format = analysis.format();
+
+        // Quirks only matter to a CHIP-8 ROM if it runs an instruction they change
+        let quirk_dependent = analysis.reachable.values().any(|&raw| matches!(
+            Self::decode_only(raw),
+            Ok(Opcode::Shr { .. } | Opcode::Shl { .. } | Opcode::Store { .. } | Opcode::Load { .. }
+                | Opcode::JpOffset { .. } | Opcode::Or { .. } | Opcode::And { .. } | Opcode::Xor { .. })
+        ));
+        // Code behind an indirect jump is hidden from the analysis, so it could use anything
+        let has_indirect_jump = analysis.reachable.values().any(|&raw| raw >> 12 == 0xB);
+        let quirk_confidence = if has_indirect_jump {
+            ConfidenceLevel::Low
+        } else if detected_format == RomFormat::Chip8 && quirk_dependent {
+            ConfidenceLevel::Medium
+        } else {
+            ConfidenceLevel::High
+        };
+
+        let rom_end = ROM_START + rom.len();
+        let mut jump_warnings: Vec<JumpWarning> = Vec::new();
+        for (&addr, &raw) in analysis.reachable.iter() {
+            let inst = Instruction::new(raw);
+            if inst.nibble != 0x1 && inst.nibble != 0x2 {
+                continue;
+            }
+            let kind = if Self::in_fontset(inst.nnn) {
+                JumpWarningKind::IntoFontset
+            } else if (inst.nnn as usize) < ROM_START || inst.nnn as usize >= rom_end {
+                JumpWarningKind::OutsideRom
+            } else if inst.nnn % 2 == 1 {
+                JumpWarningKind::Misaligned
+            } else {
+                continue;
+            };
+            jump_warnings.push(JumpWarning { addr, target: inst.nnn, kind });
+        }
+
+        let (mut chip8, suggested_quirks) = Self::from_schip_rom(rom)?;
+        chip8.enable_profiling(true);
+        // Errors end the run early, the report covers what ran up to that point
+        let _ = chip8.fast_forward(cycles);
+
+        Ok(TestReport {
+            rom_hash: Sha256::digest(rom).into(),
+            detected_format,
+            suggested_quirks,
+            quirk_confidence,
+            jump_warnings,
+            opcode_coverage: chip8.opcode_coverage(),
+            runtime_stats: Chip8Stats {
+                cycles: chip8.cycles,
+                frames_rendered: chip8.frames_rendered,
+                collisions: chip8.collision_count,
+                sound_ticks: chip8.sound_timer_total,
+                max_observed_sp: chip8.max_observed_sp,
+                spin_waits: chip8.spin_wait_count
+            },
+            final_display_hash: chip8.display_hash,
+            timing_jitter: chip8.measure_cycle_jitter()
+        })
+    }
+
+    #[cfg(feature = "http")]
+    pub fn load_rom_from_http(&mut self, url: &str) -> Result<(), Chip8Error> {
+        use std::io::Read;
+
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Chip8Error::NetworkError(format!("Unsupported URL: {}", url)));
+        }
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(10))
+            .build();
+        let response = agent.get(url).call()
+            .map_err(|err| Chip8Error::NetworkError(err.to_string()))?;
+
+        // Reject oversized ROMs before downloading them
+        let content_length = response.header("Content-Length").and_then(|len| len.parse::<usize>().ok());
+        let max_rom_size = self.max_rom_size();
+        if let Some(size) = content_length.filter(|&size| size > max_rom_size) {
+            return Err(Chip8Error::RomTooLarge { size, max: max_rom_size });
+        }
+
+        let content_type = response.content_type();
+        if content_type != "application/octet-stream" && content_type != "application/x-chip8" {
+            return Err(Chip8Error::NetworkError(format!("Unexpected Content-Type: {}", content_type)));
+        }
+
+        // Read one byte past the limit so servers without Content-Length are still caught
+        let mut data: Vec<u8> = Vec::new();
+        response.into_reader().take(max_rom_size as u64 + 1).read_to_end(&mut data)?;
+
+        self.load_rom_bytes(&data)
+    }
+
+    // Decoded bytes still need to go through load_rom_bytes
+    #[cfg(feature = "base64")]
+    pub fn import_from_base64(b64: &str) -> Result<Vec<u8>, Chip8Error> {
+        use base64::Engine;
+
+        let data = base64::engine::general_purpose::STANDARD.decode(b64.trim())
+            .map_err(|err| Chip8Error::InvalidRomData(err.to_string()))?;
+
+        // Nothing bigger fits in even the largest supported memory
+        Self::validate_rom(&data, SUPPORTED_MEMORY_SIZES[SUPPORTED_MEMORY_SIZES.len() - 1] - ROM_START)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "base64")]
+    pub fn export_rom_as_base64(&self) -> String {
+        use base64::Engine;
+
+        base64::engine::general_purpose::STANDARD.encode(&self.memory[self.rom_range()])
+    }
+
+    // Suspend/resume snapshot. Layout after the magic and version: memory, V0-VF, I, PC, the
+    // stack, SP, DT, ST, keypad bitmask, draw flag, FX0A key wait, RNG seed/stream/position,
+    // then the lo-res display, the hi-res flag and the hi-res display packed 8 pixels a byte.
+    // Version 2 adds XO-CHIP plane 2 and the plane mask, the RPL flags, the audio pitch and
+    // pattern, ROM start and size, draws this frame, the platform and the quirks. Debugging
+    // and display settings aren't part of it. Multi-byte values are little endian.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.save_state_len());
+        out.extend_from_slice(SAVE_STATE_MAGIC);
+        out.push(SAVE_STATE_VERSION);
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        for addr in self.stack {
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+        out.push(self.sp as u8);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        let keys = keys_to_mask(&self.keypad);
+        out.extend_from_slice(&keys.to_le_bytes());
+        out.push(self.draw_flag as u8);
+        out.push(self.wait_for_release as u8);
+        out.push(self.wait_key as u8);
+        out.extend_from_slice(&self.rng.get_seed());
+        out.extend_from_slice(&self.rng.get_stream().to_le_bytes());
+        out.extend_from_slice(&self.rng.get_word_pos().to_le_bytes());
+        out.extend(Self::pack_pixels(self.display.iter().flatten()));
+        out.push(self.hires as u8);
+        out.extend(Self::pack_pixels(self.hires_display.iter().flatten()));
+        out.extend(Self::pack_pixels(self.plane2.iter().flatten()));
+        out.push(self.plane_mask);
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.audio_pitch);
+        out.extend_from_slice(&self.audio_pattern);
+        out.extend_from_slice(&(self.rom_start as u32).to_le_bytes());
+        out.extend_from_slice(&(self.rom_size as u32).to_le_bytes());
+        out.push(self.draws_this_frame);
+        out.push(Self::platform_to_byte(self.platform));
+        out.extend_from_slice(&Self::quirks_to_bytes(&self.quirks));
+        out
+    }
+
+    // Everything is checked before any state is touched, a bad save leaves the emulator as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        if data.len() < 5 || &data[..4] != SAVE_STATE_MAGIC {
+            return Err(Chip8Error::InvalidSaveState("not a save state".to_string()));
+        }
+        if data[4] != SAVE_STATE_VERSION {
+            return Err(Chip8Error::InvalidSaveState(format!("unsupported version {}", data[4])));
+        }
+        if data.len() != self.save_state_len() {
+            return Err(Chip8Error::InvalidSaveState(format!("expected {} bytes, got {}", self.save_state_len(), data.len())));
+        }
+
+        let mut pos = 5;
+        let mut take = |len: usize| {
+            let bytes = &data[pos..pos + len];
+            pos += len;
+            bytes
+        };
+        let memory = take(self.memory.len());
+        let v = take(16);
+        let i = u16::from_le_bytes(take(2).try_into().unwrap());
+        let pc = u16::from_le_bytes(take(2).try_into().unwrap());
+        let stack = take(32);
+        let sp = take(1)[0] as usize;
+        if sp > self.stack.len() {
+            return Err(Chip8Error::InvalidSaveState(format!("stack pointer {} out of range", sp)));
+        }
+        let timers = take(2);
+        let keys = u16::from_le_bytes(take(2).try_into().unwrap());
+        let flags = take(3);
+        let seed: [u8; 32] = take(32).try_into().unwrap();
+        let stream = u64::from_le_bytes(take(8).try_into().unwrap());
+        let word_pos = u128::from_le_bytes(take(16).try_into().unwrap());
+        let display = take(DISPLAY_WIDTH * DISPLAY_HEIGHT / 8);
+        let hires = take(1)[0] != 0;
+        let hires_display = take(HIRES_WIDTH * HIRES_HEIGHT / 8);
+        let plane2 = take(DISPLAY_WIDTH * DISPLAY_HEIGHT / 8);
+        let plane_mask = take(1)[0];
+        if plane_mask > 3 {
+            return Err(Chip8Error::InvalidSaveState(format!("plane mask {} out of range", plane_mask)));
+        }
+        let rpl_flags = take(RPL_FLAG_COUNT);
+        let audio_pitch = take(1)[0];
+        let audio_pattern = take(16);
+        let rom_start = u32::from_le_bytes(take(4).try_into().unwrap()) as usize;
+        let rom_size = u32::from_le_bytes(take(4).try_into().unwrap()) as usize;
+        if rom_start + rom_size > self.memory.len() {
+            return Err(Chip8Error::InvalidSaveState(format!("ROM at 0x{:X} of {} bytes is outside memory", rom_start, rom_size)));
+        }
+        let draws_this_frame = take(1)[0];
+        let platform = Self::platform_from_byte(take(1)[0])
+            .ok_or_else(|| Chip8Error::InvalidSaveState("unknown platform".to_string()))?;
+        let quirks = Self::quirks_from_bytes(take(4))
+            .ok_or_else(|| Chip8Error::InvalidSaveState("unknown quirks".to_string()))?;
+
+        self.memory.copy_from_slice(memory);
+        self.v.copy_from_slice(v);
+        self.i = i;
+        self.pc = pc;
+        for (slot, bytes) in self.stack.iter_mut().zip(stack.chunks_exact(2)) {
+            *slot = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        self.sp = sp;
+        self.delay_timer = timers[0];
+        self.sound_timer = timers[1];
+        self.keypad = mask_to_keys(keys);
+        self.draw_flag = flags[0] != 0;
+        self.wait_for_release = flags[1] != 0;
+        self.wait_key = flags[2] as usize & 0xF;
+        self.rng = ChaCha8Rng::from_seed(seed);
+        self.rng.set_stream(stream);
+        self.rng.set_word_pos(word_pos);
+        for (index, pixel) in self.display.iter_mut().flatten().enumerate() {
+            *pixel = display[index / 8] & (0x80 >> (index % 8)) != 0;
+        }
+        self.hires = hires;
+        for (index, pixel) in self.hires_display.iter_mut().flatten().enumerate() {
+            *pixel = hires_display[index / 8] & (0x80 >> (index % 8)) != 0;
+        }
+        for (index, pixel) in self.plane2.iter_mut().flatten().enumerate() {
+            *pixel = plane2[index / 8] & (0x80 >> (index % 8)) != 0;
+        }
+        self.plane_mask = plane_mask;
+        self.rpl_flags.copy_from_slice(rpl_flags);
+        self.audio_pitch = audio_pitch;
+        self.audio_pattern.copy_from_slice(audio_pattern);
+        self.rom_start = rom_start;
+        self.rom_size = rom_size;
+        self.draws_this_frame = draws_this_frame;
+        self.platform = platform;
+        self.quirks = quirks;
+        self.recompute_display_hash();
+        self.display_changed = true;
+
+        Ok(())
+    }
+
+    fn save_state_len(&self) -> usize {
+        // magic + version, memory, registers, I/PC, stack, SP/DT/ST, keypad, flags, RNG, displays,
+        // plane 2 and its mask, RPL flags, audio, ROM start/size, draws, platform, quirks
+        5 + self.memory.len() + 16 + 4 + 32 + 3 + 2 + 3 + 56
+            + DISPLAY_WIDTH * DISPLAY_HEIGHT / 8 + 1 + HIRES_WIDTH * HIRES_HEIGHT / 8
+            + DISPLAY_WIDTH * DISPLAY_HEIGHT / 8 + 1 + RPL_FLAG_COUNT + 1 + 16 + 8 + 1 + 1 + 4
+    }
+
+    const fn platform_to_byte(platform: Platform) -> u8 {
+        match platform {
+            Platform::Chip8 => 0,
+            Platform::Chip48 => 1,
+            Platform::SuperChip => 2,
+            Platform::XoChip => 3
+        }
+    }
+
+    const fn platform_from_byte(byte: u8) -> Option<Platform> {
+        match byte {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::Chip48),
+            2 => Some(Platform::SuperChip),
+            3 => Some(Platform::XoChip),
+            _ => None
+        }
+    }
+
+    // The on/off quirks as bits, then the shift mode, the draw limit and the FX1E overflow behaviour
+    fn quirks_to_bytes(quirks: &Quirks) -> [u8; 4] {
+        let flags = quirks.load_store as u8
+            | (quirks.jump as u8) << 1
+            | (quirks.vf_reset as u8) << 2
+            | (quirks.clip as u8) << 3
+            | (quirks.display_wait as u8) << 4;
+        let shift_mode = match quirks.shift_mode {
+            ShiftMode::Chip8 { uses_vy: false } => 0,
+            ShiftMode::Chip8 { uses_vy: true } => 1,
+            ShiftMode::SuperChip10 => 2,
+            ShiftMode::SuperChip11 => 3
+        };
+        let i_overflow = match quirks.i_overflow {
+            IOverflowBehavior::SetVf => 0,
+            IOverflowBehavior::Ignore => 1,
+            IOverflowBehavior::Saturate => 2
+        };
+        [flags, shift_mode, quirks.max_draws_per_vblank, i_overflow]
+    }
+
+    fn quirks_from_bytes(bytes: &[u8]) -> Option<Quirks> {
+        let flag = |bit: u8| bytes[0] & (1 << bit) != 0;
+        let shift_mode = match bytes[1] {
+            0 => ShiftMode::Chip8 { uses_vy: false },
+            1 => ShiftMode::Chip8 { uses_vy: true },
+            2 => ShiftMode::SuperChip10,
+            3 => ShiftMode::SuperChip11,
+            _ => return None
+        };
+        let i_overflow = match bytes[3] {
+            0 => IOverflowBehavior::SetVf,
+            1 => IOverflowBehavior::Ignore,
+            2 => IOverflowBehavior::Saturate,
+            _ => return None
+        };
+        Some(Quirks {
+            load_store: flag(0),
+            shift_mode,
+            jump: flag(1),
+            vf_reset: flag(2),
+            clip: flag(3),
+            display_wait: flag(4),
+            max_draws_per_vblank: bytes[2],
+            i_overflow
+        })
+    }
+
+    fn pack_pixels<'a>(pixels: impl Iterator<Item = &'a bool>) -> Vec<u8> {
+        let mut packed = Vec::new();
+        for (index, &pixel) in pixels.enumerate() {
+            if index % 8 == 0 {
+                packed.push(0);
+            }
+            if pixel {
+                *packed.last_mut().unwrap() |= 0x80 >> (index % 8);
+            }
+        }
+        packed
+    }
+
+    // Decode without touching any emulator state
+    pub const fn decode_only(raw: u16) -> Result<Opcode, Chip8Error> {
+        let inst = Instruction::new(raw);
+        let (x, y) = (inst.x, inst.y);
+        let opcode = match inst.nibble {
+            0x0 => match inst.nn {
+                0xE0 if x == 0 => Opcode::Cls,
+                0xEE if x == 0 => Opcode::Ret,
+                0xC0..=0xCF if x == 0 => Opcode::ScrollDown { n: inst.n },
+                0xFB if x == 0 => Opcode::ScrollRight,
+                0xFC if x == 0 => Opcode::ScrollLeft,
+                0xFE if x == 0 => Opcode::Lores,
+                0xFF if x == 0 => Opcode::Hires,
+                _ => return Err(Chip8Error::InvalidOpcode(raw))
+            },
+            0x1 => Opcode::Jp(inst.nnn),
+            0x2 => Opcode::Call(inst.nnn),
+            0x3 => Opcode::SeByte { x, nn: inst.nn },
+            0x4 => Opcode::SneByte { x, nn: inst.nn },
+            0x5 => match inst.n {
+                0x0 => Opcode::SeReg { x, y },
+                0x2 => Opcode::StoreRange { x, y },
+                0x3 => Opcode::LoadRange { x, y },
+                _ => return Err(Chip8Error::InvalidOpcode(raw))
+            },
+            0x6 => Opcode::LdByte { x, nn: inst.nn },
+            0x7 => Opcode::AddByte { x, nn: inst.nn },
+            0x8 => match inst.n {
+                0x0 => Opcode::LdReg { x, y },
+                0x1 => Opcode::Or { x, y },
+                0x2 => Opcode::And { x, y },
+                0x3 => Opcode::Xor { x, y },
+                0x4 => Opcode::AddReg { x, y },
+                0x5 => Opcode::Sub { x, y },
+                0x6 => Opcode::Shr { x, y },
+                0x7 => Opcode::Subn { x, y },
+                0xE => Opcode::Shl { x, y },
+                _ => return Err(Chip8Error::InvalidOpcode(raw))
+            },
+            0x9 if inst.n == 0x0 => Opcode::SneReg { x, y },
+            0xA => Opcode::LdI(inst.nnn),
+            0xB => Opcode::JpOffset { x, nnn: inst.nnn },
+            0xC => Opcode::Rnd { x, nn: inst.nn },
+            0xD => Opcode::Drw { x, y, n: inst.n },
+            0xE => match inst.nn {
+                0x9E => Opcode::Skp { x },
+                0xA1 => Opcode::Sknp { x },
+                _ => return Err(Chip8Error::InvalidOpcode(raw))
+            },
+            0xF => match inst.nn {
+                0x00 if x == 0 => Opcode::LdILong,
+                0x01 => Opcode::Plane { mask: x as u8 },
+                0x07 => Opcode::LdVxDt { x },
+                0x0A => Opcode::LdKey { x },
+                0x15 => Opcode::LdDtVx { x },
+                0x18 => Opcode::LdStVx { x },
+                0x1E => Opcode::AddI { x },
+                0x29 => Opcode::LdFont { x },
+                0x30 => Opcode::LdBigFont { x },
+                0x33 => Opcode::Bcd { x },
+                0x55 => Opcode::Store { x },
+                0x65 => Opcode::Load { x },
+                0x75 => Opcode::StoreFlags { x },
+                0x85 => Opcode::LoadFlags { x },
+                _ => return Err(Chip8Error::InvalidOpcode(raw))
+            },
+            _ => return Err(Chip8Error::InvalidOpcode(raw))
+        };
+        Ok(opcode)
+    }
+
+    // Jumps, calls, returns and skips
+    pub fn is_control_flow_opcode(raw: u16) -> bool {
+        matches!(
+            Self::decode_only(raw),
+            Ok(Opcode::Ret | Opcode::Jp(_) | Opcode::Call(_) | Opcode::JpOffset { .. }
+                | Opcode::SeByte { .. } | Opcode::SneByte { .. } | Opcode::SeReg { .. }
+                | Opcode::SneReg { .. } | Opcode::Skp { .. } | Opcode::Sknp { .. })
+        )
+    }
+
+    // Errors rather than wrapping around when the PC runs off the end of memory
+    pub fn fetch(&mut self) -> Result<Instruction, Chip8Error> {
+        let pc = self.pc as usize;
+        let (Some(&high), Some(&low)) = (self.memory.get(pc), self.memory.get(pc + 1)) else {
+            return Err(Chip8Error::PcOutOfBounds(self.pc));
+        };
+        let mut inst = Instruction::new((high as u16) << 8 | low as u16);
+        self.fetch_pc = self.pc;
+        self.skip_instruction()?;
+
+        // Pull in the second word of 4 byte instructions
+        if inst.encoding_size(self.platform) == 4 {
+            let pc = self.pc as usize;
+            if let (Some(&high), Some(&low)) = (self.memory.get(pc), self.memory.get(pc + 1)) {
+                inst.extra = Some((high as u16) << 8 | low as u16);
+            }
+            self.skip_instruction()?;
+        }
+        Ok(inst)
+    }
+
+    // Moves the PC past one 2 byte instruction. With 64KB of memory there's nowhere to go after 0xFFFE.
+    fn skip_instruction(&mut self) -> Result<(), Chip8Error> {
+        self.pc = self.pc.checked_add(2).ok_or(Chip8Error::PcOutOfBounds(self.pc))?;
+        Ok(())
+    }
+
+    // XO-CHIP F000 NNNN: I = NNNN, which has to fall inside memory (4KB unless extended)
+    pub fn handle_f000(&mut self, inst: &Instruction) -> Result<u8, Chip8Error> {
+        let Some(addr) = inst.extra else {
+            return Err(Chip8Error::InvalidOpcode(inst.instruction));
+        };
+        if addr as usize >= self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds(addr as usize));
+        }
+        self.i = addr;
+        Ok(SUCCESSFUL_EXECUTION)
+    }
+
+    pub fn execute(&mut self, inst: Instruction) -> Result<u8, Chip8Error> {
+        if self.profiling && let Some(profile) = self.profile.as_mut() {
+            profile.record(inst.instruction);
+        }
+
+        // User-defined opcodes take priority over the built-in ones
+        if let Some(handler) = self.extensions.get_mut(&inst.instruction) {
+            let mut state = Chip8State {
+                memory: &mut self.memory,
+                v: &mut self.v,
+                i: &mut self.i,
+                pc: &mut self.pc,
+                display: &mut self.display,
+                draw_flag: &mut self.draw_flag
+            };
+            handler.execute(&inst, &mut state)?;
+
+            // Extensions can write to the display directly, so the hash can't be kept up incrementally
+            if self.draw_flag {
+                self.recompute_display_hash();
+            }
+            return Ok(SUCCESSFUL_EXECUTION);
+        }
+
+        match Self::decode_only(inst.instruction) {
+            Ok(opcode) => {
+                self.opcodes_executed.insert(opcode.class());
+            }
+            Err(_) if self.break_on_unknown => {
+                self.last_unknown_opcode = Some(inst.instruction);
+                self.paused = true;
+                return Ok(SUCCESSFUL_EXECUTION);
+            }
+            Err(_) => {}
+        }
+
+        // Opcode families on top of the original instruction set
+        let superchip = matches!(self.platform, Platform::SuperChip | Platform::XoChip);
+        let xochip = self.platform == Platform::XoChip;
+
+        // Execute
+        match inst.nibble {
+            0x0 => {
+                match inst.nn {
+                    0xE0 => {
+                        // Clear display, only the selected planes on XO-CHIP
+                        for plane in 0..2 {
+                            if self.plane_mask & (1 << plane) == 0 {
+                                continue;
+                            }
+                            let target = self.plane_mut(plane);
+                            let had_pixels = target.iter().flatten().any(|&pixel| pixel);
+                            *target = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+                            if had_pixels {
+                                self.display_changed = true;
+                                self.recompute_display_hash();
+                            }
+                        }
+                        if self.hires && self.hires_display.iter().flatten().any(|&pixel| pixel) {
+                            self.hires_display = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+                            self.display_changed = true;
+                            self.recompute_display_hash();
+                        }
+                    },
+                    0xEE => {
+                        // Return from main (close ROM)
+                        if self.sp == 0 {
+                            if self.wait_for_initial_ret {
+                                return Ok(EXIT_ROM);
+                            }
+                            return Err(Chip8Error::StackUnderflow);
+                        }
+                        // return from sub function
+                        self.sp -= 1;
+                        self.pc = self.stack[self.sp];
+                    },
+                    nn if nn & 0xF0 == 0xC0 && superchip => {
+                        // SUPER-CHIP 00CN: scroll down N rows
+                        self.scroll_display(0, inst.n as usize);
+                    },
+                    0xFB if superchip => {
+                        // SUPER-CHIP 00FB: scroll right 4 pixels
+                        self.scroll_display(4, 0);
+                    },
+                    0xFC if superchip => {
+                        // SUPER-CHIP 00FC: scroll left 4 pixels
+                        self.scroll_display(-4, 0);
+                    },
+                    0xFE | 0xFF if superchip => {
+                        // SUPER-CHIP 00FE/00FF: back to 64x32 or up to 128x64, both start blank
+                        self.hires = inst.nn == 0xFF;
+                        self.display = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+                        self.plane2 = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+                        self.hires_display = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+                        self.display_hash = DISPLAY_HASH_SEED;
+                        self.display_changed = true;
+                        self.draw_flag = true;
+                    },
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            0x1 => {
+                // Jump: PC = NNN
+                self.pc = inst.nnn;
+            }
+            0x2 => {
+                // JAL: STACK[SP] = PC AND PC = NNN
+                if self.sp >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow);
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = inst.nnn;
+            }
+            0x3 => {
+                // Skip 1 instruction if VX == NN
+                if self.v[inst.x] == inst.nn {
+                    self.skip_instruction()?;
+                }
+            }
+            0x4 => {
+                // Skip 1 instruction if VX != NN
+                if self.v[inst.x] != inst.nn {
+                    self.skip_instruction()?;
+                }
+            }
+            0x5 => {
+                match inst.n {
                     0x0 => {
                         // Skip 1 instruction if VX == VY
                         if self.v[inst.x] == self.v[inst.y] {
-                            self.pc += 2;
+                            self.skip_instruction()?;
+                        }
+                    }
+                    0x2 | 0x3 if xochip => {
+                        // XO-CHIP: 5XY2 stores VX-VY at I, 5XY3 loads them back. I never moves.
+                        if inst.x > inst.y {
+                            return Err(Chip8Error::InvalidOpcode(inst.instruction));
+                        }
+                        self.validate_memory_range(self.i, inst.y - inst.x + 1)?;
+                        let start = self.i as usize;
+                        let end = start + inst.y - inst.x;
+                        if inst.n == 0x2 {
+                            self.memory[start..=end].copy_from_slice(&self.v[inst.x..=inst.y]);
+                        } else {
+                            for reg in inst.x..=inst.y {
+                                self.write_v(reg, self.memory[start + reg - inst.x]);
+                            }
+                        }
+                    }
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            0x6 => {
+                // SET: VX = NN
+                self.write_v(inst.x, inst.nn);
+            }
+            0x7 => {
+                // ADD: VX = VX + NN
+                let (result, _) = self.v[inst.x].overflowing_add(inst.nn);
+                self.write_v(inst.x, result);
+            }
+            0x8 => {
+                match inst.n {
+                    // BINARY OPS
+                    0x0 => {
+                        // SET: VX = VY
+                        self.write_v(inst.x, self.v[inst.y]);
+                    }
+                    0x1 => {
+                        // OR: VX = VX OR VY
+                        self.write_v(inst.x, self.v[inst.x] | self.v[inst.y]);
+                        if self.quirks.vf_reset {
+                            self.write_v(0xF, 0); 
+                        }
+                    }
+                    0x2 => {
+                        // AND: VX = VX AND VY
+                        self.write_v(inst.x, self.v[inst.x] & self.v[inst.y]);
+                        if self.quirks.vf_reset {
+                            self.write_v(0xF, 0); 
+                        }
+                    }
+                    0x3 => {
+                        // XOR: VX = VX XOR VY
+                        self.write_v(inst.x, self.v[inst.x] ^ self.v[inst.y]);
+                        if self.quirks.vf_reset {
+                            self.write_v(0xF, 0); 
+                        }
+                    }
+                    // The flag ops below read their operands up front and write VF last, so
+                    // with X = F (e.g. 8FF4) VF ends up holding the flag rather than the result
+                    0x4 => {
+                        // ADD (with overflow): VX = VX + VY
+                        let (sum, carry) = self.v[inst.x].overflowing_add(self.v[inst.y]);
+                        self.write_v(inst.x, sum);
+                        self.write_v(0xF, if carry { 1 } else { 0 });
+                    }
+                    0x5 => {
+                        // 8XY5 sets VX to the result of VX - VY.                        
+                        let (result, borrowed) = self.v[inst.x].overflowing_sub(self.v[inst.y]);
+                        self.write_v(inst.x, result);
+                        self.write_v(0xF, if borrowed { 0 } else { 1 });
+                    }
+                    0x6 => {
+                        // SHIFT QUIRK: 8XY6 VX = VY >> 1
+                        // No QUIRK:    8XY6 VX = VX >> 1
+                        let shift_src = match self.quirks.shift_mode {
+                            ShiftMode::Chip8 { uses_vy: true } | ShiftMode::SuperChip10 => inst.y,
+                            ShiftMode::Chip8 { uses_vy: false } | ShiftMode::SuperChip11 => inst.x
+                        };
+                        let value = self.v[shift_src];
+                        self.write_v(inst.x, value >> 1);
+                        self.write_v(0xF, value & 0x1);
+                    }
+                    0x7 => {
+                        // 8XY7 sets VX to the result of VY - VX.
+                        let (result, borrowed) = self.v[inst.y].overflowing_sub(self.v[inst.x]);
+                        self.write_v(inst.x, result);
+                        self.write_v(0xF, if borrowed { 0 } else { 1 });
+                    }
+                    0xE => {
+                        // YSHIFT:    8XYE VX = VY << 1
+                        // No YSHIFT: 8XYE VX = VX << 1
+                        let shift_src = match self.quirks.shift_mode {
+                            ShiftMode::Chip8 { uses_vy: true } | ShiftMode::SuperChip10 => inst.y,
+                            ShiftMode::Chip8 { uses_vy: false } | ShiftMode::SuperChip11 => inst.x
+                        };
+                        let value = self.v[shift_src];
+                        self.write_v(inst.x, value << 1);
+                        self.write_v(0xF, (value >> 7) & 0x1);
+                    }
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            0x9 => {
+                match inst.n {
+                    0x0 => {
+                        // Skip 1 instruction if VX != VY
+                        if self.v[inst.x] != self.v[inst.y] {
+                            self.skip_instruction()?;
+                        }
+                    }
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            0xA => {
+                // I = NNN
+                self.i = inst.nnn;
+            }
+            0xB => {
+                // CHIP-8:     0xBNNN Jump to NNN + V0
+                // SUPER-CHIP: 0xBXNN Jump to XNN + VX
+                let v_src = if self.quirks.jump { inst.x } else { 0 };
+                self.pc = inst.nnn + self.v[v_src] as u16;
+            }
+            0xC => {
+                // VX = random number bitwise & with NN
+                let random_byte: u8 = (self.rng.next_u32() & 0xFF) as u8;
+                self.write_v(inst.x, random_byte & inst.nn);
+            }
+            0xD => {
+                // Drop draws past the per-frame limit, the timers tick over to the next frame
+                let max_draws = self.quirks.max_draws_per_vblank;
+                if max_draws > 0 {
+                    if self.draws_this_frame >= max_draws {
+                        self.draw_flag = false;
+                        return Ok(SUCCESSFUL_EXECUTION);
+                    }
+                    self.draws_this_frame += 1;
+                }
+
+                // Original COSMAC VIP waited for VBlank before drawing
+                if self.quirks.display_wait && !self.fast_forward_mode {
+                    self.wait_for_vblank();
+                }
+
+                // Alter Display
+                let (width, height) = if self.hires { (HIRES_WIDTH, HIRES_HEIGHT) } else { (DISPLAY_WIDTH, DISPLAY_HEIGHT) };
+                let x_coord = self.v[inst.x] as usize % width;
+                let y_coord = self.v[inst.y] as usize % height;
+                // SUPER-CHIP DXY0 draws a 16x16 sprite, two bytes per row
+                let (sprite_rows, sprite_width) = if inst.n == 0 && superchip { (16, 16) } else { (inst.n as usize, 8) };
+                let bytes_per_plane = sprite_rows * sprite_width / 8;
+                let planes_drawn = if self.hires { 1 } else { self.plane_mask.count_ones() as usize };
+                self.validate_memory_range(self.i, bytes_per_plane * planes_drawn)?;
+                let mut collided = false;
+
+                // XO-CHIP draws to each selected plane in turn, the sprite data for
+                // the next plane follows straight on from the previous one
+                let mut sprite_start = self.i as usize;
+                for plane in 0..2 {
+                    // Hi-res only has the one plane
+                    if self.plane_mask & (1 << plane) == 0 || (self.hires && plane > 0) {
+                        continue;
+                    }
+
+                    for index in 0..sprite_rows {
+                        let sprite_row = if sprite_width == 16 {
+                            u16::from_be_bytes([self.memory[sprite_start + index * 2], self.memory[sprite_start + index * 2 + 1]])
+                        } else {
+                            (self.memory[sprite_start + index] as u16) << 8
+                        };
+
+                        // Y-coordinate handling
+                        let pixel_y = y_coord + index;
+                        if self.quirks.clip && pixel_y >= height {
+                            continue; // skip drawing if clipped vertically
+                        }
+                        if !self.hires && self.debug_overlay_enabled && pixel_y % DISPLAY_HEIGHT >= DEBUG_OVERLAY_START_ROW {
+                            continue; // rows under the debug overlay are masked off
+                        }
+                        if !self.hires && pixel_y % DISPLAY_HEIGHT >= self.display_rows {
+                            continue; // rows reserved for the audio visualization
+                        }
+
+                        for bit_index in 0..sprite_width {
+                            let pixel_x = x_coord + bit_index;
+                            if self.quirks.clip && pixel_x >= width {
+                                continue; // skip drawing if clipped horizontally
+                            }
+
+                            // Apply wrapping if clipping is off
+                            let px = if self.quirks.clip {
+                                pixel_x
+                            } else {
+                                pixel_x % width
+                            };
+                            let py = if self.quirks.clip {
+                                pixel_y
+                            } else {
+                                pixel_y % height
+                            };
+
+                            let sprite_pixel_on = (sprite_row >> (15 - bit_index)) & 1 == 1;
+
+                            if sprite_pixel_on {
+                                let pixel = if self.hires {
+                                    &mut self.hires_display[py][px]
+                                } else {
+                                    &mut self.plane_mut(plane)[py][px]
+                                };
+                                let current_pixel = *pixel;
+                                *pixel ^= true;
+                                self.display_hash ^= if self.hires {
+                                    Self::hires_pixel_hash(px, py)
+                                } else {
+                                    Self::pixel_hash(plane, px, py)
+                                };
+
+                                collided |= current_pixel;
+                                self.display_changed = true;
+                            }
+                        }
+                    }
+                    sprite_start += bytes_per_plane;
+                }
+                self.write_v(0xF, collided as u8);
+                if collided {
+                    self.collision_count += 1;
+                }
+                self.frames_rendered += 1;
+                if self.display_lag_frames > 0 {
+                    self.pending_draw = true; // Flagged once the lag has passed
+                } else {
+                    self.draw_flag = true;
+                }
+            }
+            0xE => {
+                // Only keys 0-F exist, a higher VX is as broken as an unknown opcode
+                let Some(&pressed) = self.keypad.get(self.v[inst.x] as usize) else {
+                    return Err(Chip8Error::InvalidOpcode(inst.instruction));
+                };
+                match inst.nn {
+                    0x9E => {
+                        // Skip next instruction if X key is pressed
+                        if pressed {
+                            self.skip_instruction()?;
+                        }
+                    }
+                    0xA1 => {
+                        // Skip next instruction if X key is NOT pressed
+                        if !pressed {
+                            self.skip_instruction()?;
+                        }
+                    }
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            0xF => {
+                match inst.nn {
+                    0x00 if inst.instruction == 0xF000 && xochip => {
+                        return self.handle_f000(&inst);
+                    }
+                    0x01 if xochip => {
+                        // XO-CHIP FN01: select the planes later draws and clears go to
+                        self.set_plane_write_mask(inst.x as u8)?;
+                    }
+                    // Timer Instructions
+                    0x07 => {
+                        // Set VX to current value of Delay Timer
+                        self.write_v(inst.x, self.delay_timer);
+                    }
+                    0x0A => {
+                        if !self.wait_for_release { // If not actively waiting for key release
+                            // Check if any button is pressed
+                            for (i, &pressed) in self.keypad.iter().enumerate() {
+                                if pressed {
+                                    self.wait_for_release = true;
+                                    self.wait_key = i;
+                                    break;
+                                }
+                            }
+                        } 
+                        // If waiting for initial key press or key release
+                        if !self.wait_for_release || self.keypad[self.wait_key] {
+                            // Don't advance to next instruction
+                            self.pc = self.pc.checked_sub(2).ok_or(Chip8Error::PcOutOfBounds(self.pc))?;
+                        } else { // The key was let go
+                            self.write_v(inst.x, self.wait_key as u8);
+                            self.wait_for_release = false;
+                        }
+                    }
+                    0x15 => {
+                        // Sets the Delay Timer to the value in VX
+                        self.delay_timer = self.v[inst.x];
+                    }
+                    0x18 => {
+                        // Sets the sound timer to the value in VX
+                        self.sound_timer = self.v[inst.x];
+                    }
+                    0x1E => {
+                        // I = I + VX, overflowing once it passes the last address (0xFFF unless extended)
+                        let sum = self.i as u32 + self.v[inst.x] as u32;
+                        let last_addr = self.memory.len() as u32 - 1;
+                        match self.quirks.i_overflow {
+                            IOverflowBehavior::SetVf => {
+                                self.i = sum as u16;
+                                self.write_v(0xF, if sum > last_addr { 1 } else { 0 });
+                            }
+                            IOverflowBehavior::Ignore => self.i = sum as u16,
+                            IOverflowBehavior::Saturate => self.i = sum.min(last_addr) as u16
+                        }
+                    }
+                    0x29 => {
+                        // I = location of sprite for digit in VX
+                        self.i = match self.fx29_override {
+                            Some(f) => f(self.v[inst.x] & 0xF),
+                            None => FONTSET_START as u16 + (self.v[inst.x] as u16 * 5)
+                        };
+                    }
+                    0x30 => {
+                        // SCHIP: I = location of the large sprite for digit in VX
+                        let digit = self.v[inst.x] & 0xF;
+                        self.i = match self.fx30_override {
+                            Some(f) => f(digit),
+                            None => BIG_FONTSET_START as u16 + (digit.min(9) as u16 * 10)
+                        };
+                    }
+                    0x33 => {
+                        // Store number in VX as three decimal digits, and stores in mem at location in reg I
+                        let value = self.v[inst.x];
+                        self.validate_memory_range(self.i, 3)?;
+                        let i = self.i as usize;
+                        self.memory[i..i + 3].copy_from_slice(&[value / 100, (value % 100) / 10, value % 10]);
+                    }
+                    0x55 => {
+                        // Store V0-VX variables in memory
+                        self.validate_memory_range(self.i, inst.x + 1)?;
+                        let i = self.i as usize;
+                        self.memory[i..=i + inst.x].copy_from_slice(&self.v[..=inst.x]);
+                        // Original Chip-8 incremented I, but modern don't update I
+                        if self.quirks.load_store {
+                            self.i = self.i.wrapping_add(inst.x as u16 + 1);
+                        }
+                    }
+                    0x65 => {
+                        // Loads from memory variables into V0-VX
+                        self.validate_memory_range(self.i, inst.x + 1)?;
+                        let i = self.i as usize;
+                        for reg in 0..=inst.x {
+                            self.write_v(reg, self.memory[i + reg]);
+                        }
+                        // Original Chip-8 incremented I, but modern don't update I
+                        if self.quirks.load_store {
+                            self.i = self.i.wrapping_add(inst.x as u16 + 1);
+                        }
+                    }
+                    0x75 if superchip => {
+                        // SCHIP: Store V0-VX in the RPL flags, there are only 8 of them
+                        let last = inst.x.min(RPL_FLAG_COUNT - 1);
+                        self.rpl_flags[..=last].copy_from_slice(&self.v[..=last]);
+                    }
+                    0x85 if superchip => {
+                        // SCHIP: Load V0-VX back from the RPL flags
+                        let last = inst.x.min(RPL_FLAG_COUNT - 1);
+                        for reg in 0..=last {
+                            self.write_v(reg, self.rpl_flags[reg]);
+                        }
+                    }
+                    _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+                }
+            }
+            _ => return Err(Chip8Error::InvalidOpcode(inst.instruction))
+        }
+        Ok(SUCCESSFUL_EXECUTION)        
+    }
+
+    // For opcodes that touch len bytes from base (usually I), so a bad I from a broken ROM
+    // comes back as an error rather than a panic. Reports the first address outside memory.
+    fn validate_memory_range(&self, base: u16, len: usize) -> Result<(), Chip8Error> {
+        if base as usize + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds((base as usize).max(self.memory.len())));
+        }
+        Ok(())
+    }
+
+    // Runs on a detached copy, so the random opcodes never touch this emulator
+    pub fn stress_test(&self, duration: Duration) -> StressResult {
+        let mut errors: HashMap<String, u64> = HashMap::new();
+        for category in ["invalid_opcodes", "stack_overflows", "stack_underflows", "memory_violations", "panics_caught", "other"] {
+            errors.insert(category.to_string(), 0);
+        }
+
+        let mut chip8 = self.detached_copy();
+        // Compared afterwards to spot corruption, custom fonts included
+        let fontset_before = chip8.memory[FONTSET_START..FONTSET_START + DEFAULT_FONTSET.len()].to_vec();
+
+        // Separate RNG so the emulator's own random sequence is left alone
+        let mut opcode_rng = ChaCha8Rng::seed_from_u64(0);
+        let mut total: u64 = 0;
+
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            let raw = (opcode_rng.next_u32() & 0xFFFF) as u16;
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                chip8.execute(Instruction::new(raw))
+            }));
+
+            let category = match result {
+                Ok(Ok(_)) => None,
+                Ok(Err(err)) => Some(Self::stress_category(&err)),
+                Err(_) => Some("panics_caught")
+            };
+            if let Some(category) = category {
+                *errors.entry(category.to_string()).or_insert(0) += 1;
+            }
+            total += 1;
+        }
+
+        let final_memory_ok = chip8.memory[FONTSET_START..FONTSET_START + DEFAULT_FONTSET.len()] == fontset_before[..];
+
+        StressResult { total, errors, final_memory_ok }
+    }
+
+    // The buckets the stress test counts, anything else an opcode can return goes under "other"
+    fn stress_category(err: &Chip8Error) -> &'static str {
+        match err {
+            Chip8Error::InvalidOpcode(_) | Chip8Error::InvalidPlaneMask(_) => "invalid_opcodes",
+            Chip8Error::MemoryOutOfBounds(_) | Chip8Error::PcOutOfBounds(_) => "memory_violations",
+            Chip8Error::StackOverflow => "stack_overflows",
+            Chip8Error::StackUnderflow => "stack_underflows",
+            _ => "other"
+        }
+    }
+
+    pub fn enable_register_trace(&mut self, cap: usize) {
+        self.register_trace = VecDeque::with_capacity(cap);
+        self.register_trace_cap = cap;
+    }
+
+    pub fn register_trace(&self) -> &VecDeque<RegWrite> {
+        &self.register_trace
+    }
+
+    pub fn register_state_changed_hook(&mut self, hook: fn(reg: u8, old: u8, new: u8, pc: u16)) {
+        self.register_hook = Some(hook);
+    }
+
+    // Same as add_register_watchpoint, for the u8 register numbers RegWrite uses
+    pub fn register_write_watchpoint(&mut self, reg: u8, value: u8) {
+        self.add_register_watchpoint(reg as usize, value);
+    }
+
+    pub fn add_register_watchpoint(&mut self, reg: usize, value: u8) {
+        self.watchpoints.push(Watchpoint::Register { reg: reg & 0xF, value });
+    }
+
+    pub fn add_memory_watchpoint(&mut self, addr: u16, value: u8) {
+        self.watchpoints.push(Watchpoint::Memory { addr, value });
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    fn watched_value(&self, watchpoint: &Watchpoint) -> Option<u8> {
+        match *watchpoint {
+            Watchpoint::Register { reg, .. } => Some(self.v[reg]),
+            Watchpoint::Memory { addr, .. } => self.memory.get(addr as usize).copied()
+        }
+    }
+
+    // Register watchpoints were already caught by write_v. Memory ones only fire on the
+    // instruction that changed the byte, so carrying on doesn't stop again straight away.
+    fn triggered_watchpoint(&mut self, before: &[Option<u8>]) -> Option<Watchpoint> {
+        if let Some(watchpoint) = self.register_watch_hit.take() {
+            return Some(watchpoint);
+        }
+        self.watchpoints.iter().zip(before).find_map(|(watchpoint, &old_val)| match *watchpoint {
+            Watchpoint::Memory { value, .. } => {
+                let new_val = self.watched_value(watchpoint);
+                (new_val != old_val && new_val == Some(value)).then_some(*watchpoint)
+            }
+            Watchpoint::Register { .. } => None
+        })
+    }
+
+    // Every V register write in execute goes through here, so the trace, hook and
+    // watchpoints see it even when the value written is the one already there
+    fn write_v(&mut self, reg: usize, value: u8) {
+        let old_val = self.v[reg];
+        self.v[reg] = value;
+        if self.register_trace_cap == 0 && self.register_hook.is_none() && self.watchpoints.is_empty() {
+            return;
+        }
+
+        let write = RegWrite { reg: reg as u8, old_val, new_val: value, pc: self.fetch_pc };
+        if self.register_trace_cap > 0 {
+            if self.register_trace.len() >= self.register_trace_cap {
+                self.register_trace.pop_front();
+            }
+            self.register_trace.push_back(write);
+        }
+        if let Some(hook) = self.register_hook.filter(|_| !self.fast_forward_mode) {
+            hook(write.reg, old_val, value, write.pc);
+        }
+        let watchpoint = Watchpoint::Register { reg, value };
+        if self.register_watch_hit.is_none() && self.watchpoints.contains(&watchpoint) {
+            self.register_watch_hit = Some(watchpoint);
+        }
+    }
+
+    // Keeps the last capacity cycles in a ring buffer, 0 turns tracing back off
+    pub fn enable_tracing(&mut self, capacity: usize) {
+        self.trace_log = VecDeque::with_capacity(capacity);
+        self.trace_log_cap = capacity;
+    }
+
+    pub fn get_trace(&self) -> Option<&VecDeque<TraceEntry>> {
+        (self.trace_log_cap > 0).then_some(&self.trace_log)
+    }
+
+    fn record_trace(&mut self, pc: u16, raw: u16) {
+        if self.trace_log_cap == 0 {
+            return;
+        }
+        if self.trace_log.len() >= self.trace_log_cap {
+            self.trace_log.pop_front();
+        }
+        self.trace_log.push_back(TraceEntry { cycle: self.total_cycles, pc, raw, v: self.v, i: self.i, sp: self.sp as u8 });
+    }
+
+    fn trace_mnemonic(raw: u16) -> String {
+        match Self::decode_only(raw) {
+            Ok(opcode) => format!("{:?}", opcode),
+            Err(_) => format!("UNKNOWN {:04X}", raw)
+        }
+    }
+
+    // Last 50 trace entries as a Markdown table, ready to paste into an issue.
+    // Registers that didn't change since the previous row are shown as a dash.
+    pub fn export_trace_as_markdown_table(&self) -> String {
+        const MAX_ROWS: usize = 50;
+
+        let mut out = String::from("```markdown\n| PC | Opcode | Mnemonic |");
+        for reg in 0..16 {
+            out.push_str(&format!(" V{:X} |", reg));
+        }
+        out.push_str(" I | SP |\n|---|---|---|");
+        out.push_str(&"---|".repeat(18));
+        out.push('\n');
+
+        let skip = self.trace_log.len().saturating_sub(MAX_ROWS);
+        let mut previous: Option<&TraceEntry> = None;
+        for entry in self.trace_log.iter().skip(skip) {
+            out.push_str(&format!("| {:04X} | {:04X} | {} |", entry.pc, entry.raw, Self::trace_mnemonic(entry.raw)));
+            for reg in 0..16 {
+                match previous {
+                    Some(prev) if prev.v[reg] == entry.v[reg] => out.push_str(" — |"),
+                    _ => out.push_str(&format!(" {:02X} |", entry.v[reg]))
+                }
+            }
+            out.push_str(&format!(" {:04X} | {:02X} |\n", entry.i, entry.sp));
+            previous = Some(entry);
+        }
+
+        out.push_str("```\n");
+        out
+    }
+
+    pub fn export_trace_as_csv(&self) -> String {
+        let mut out = String::from("cycle,pc,opcode,mnemonic");
+        for reg in 0..16 {
+            out.push_str(&format!(",v{:x}", reg));
+        }
+        out.push_str(",i,sp\n");
+
+        for entry in self.trace_log.iter() {
+            out.push_str(&format!("{},{:04X},{:04X},\"{}\"", entry.cycle, entry.pc, entry.raw, Self::trace_mnemonic(entry.raw)));
+            for value in entry.v.iter() {
+                out.push_str(&format!(",{:02X}", value));
+            }
+            out.push_str(&format!(",{:04X},{:02X}\n", entry.i, entry.sp));
+        }
+        out
+    }
+
+    // Calls handler_addr the same way 2NNN would, a 00EE in the handler picks up where it left off.
+    // Safe to use between cycles, e.g. from a flag set by a GPIO interrupt.
+    pub fn inject_nmi(&mut self, handler_addr: u16) -> Result<(), Chip8Error> {
+        if self.sp >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow);
+        }
+        self.stack[self.sp] = self.pc;
+        self.sp += 1;
+        self.pc = handler_addr;
+        self.nmi_count += 1;
+        Ok(())
+    }
+
+    pub fn nmi_count(&self) -> u64 {
+        self.nmi_count
+    }
+
+    // Pauses on unknown opcodes rather than printing or panicking, PC is left past the opcode
+    pub fn break_on_unknown_opcode(&mut self, enabled: bool) {
+        self.break_on_unknown = enabled;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    // Lets the next cycle() run the instruction it stopped on instead of hitting the same breakpoint again
+    pub fn continue_from_breakpoint(&mut self) {
+        self.skip_breakpoint = true;
+    }
+
+    // When disabled, a RET at the top level is a StackUnderflow rather than the ROM exiting
+    pub fn set_wait_for_initial_ret(&mut self, enabled: bool) {
+        self.wait_for_initial_ret = enabled;
+    }
+
+    pub fn last_unknown_opcode(&self) -> Option<u16> {
+        self.last_unknown_opcode
+    }
+
+    pub fn halt(&mut self) -> bool {
+        let was_running = self.running;
+        self.running = false;
+        was_running
+    }
+
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Lets cycle() recover from errors instead of ending the ROM. Halted, breakpoints and watchpoints always get passed through.
+    pub fn set_error_recovery(&mut self, handler: fn(&Chip8Error, &mut Chip8) -> RecoveryAction) {
+        self.error_recovery = Some(handler);
+    }
+
+    pub fn cycle(&mut self) -> Result<u8, Chip8Error> {
+        let pc_before = self.pc;
+        let result = self.run_cycle();
+
+        let Some(handler) = self.error_recovery else {
+            return result;
+        };
+        match result {
+            Err(Chip8Error::Halted | Chip8Error::BreakpointHit(_) | Chip8Error::WatchpointHit { .. }) => result,
+            Err(err) => match handler(&err, self) {
+                RecoveryAction::Abort => Err(err),
+                RecoveryAction::SkipInstruction => {
+                    // Step over the whole instruction, which is 4 bytes for XO-CHIP's F000 NNNN
+                    let pc = pc_before as usize;
+                    let size = match (self.memory.get(pc), self.memory.get(pc + 1)) {
+                        (Some(&high), Some(&low)) => Instruction::new((high as u16) << 8 | low as u16).encoding_size(self.platform),
+                        _ => 2
+                    };
+                    self.pc = pc_before.wrapping_add(size as u16);
+                    Ok(SUCCESSFUL_EXECUTION)
+                }
+                RecoveryAction::Reset => {
+                    self.reset();
+                    Ok(SUCCESSFUL_EXECUTION)
+                }
+                RecoveryAction::Ignore => Ok(SUCCESSFUL_EXECUTION)
+            },
+            result => result
+        }
+    }
+
+    // One fetch and execute, with the ROM exiting and debugger stops as results rather than
+    // exit codes and errors. Only real faults come back as Err.
+    pub fn step(&mut self) -> Result<StepResult, Chip8Error> {
+        match self.cycle() {
+            Ok(EXIT_ROM) => Ok(StepResult::ExitRom),
+            Ok(_) => Ok(StepResult::Continue),
+            Err(Chip8Error::Halted) => Ok(StepResult::Halted),
+            Err(Chip8Error::BreakpointHit(addr)) => Ok(StepResult::BreakpointHit(addr)),
+            Err(Chip8Error::WatchpointHit { watchpoint }) => Ok(StepResult::WatchpointHit(watchpoint)),
+            Err(err) => Err(err)
+        }
+    }
+
+    // Up to n cycles back to back, stopping early on an error or a non-zero exit code.
+    // draw_flag stays set if any of them drew, even if a later one cleared it.
+    pub fn cycle_n(&mut self, n: u32) -> Result<u8, Chip8Error> {
+        let mut drew = false;
+        let mut result = Ok(StepResult::Continue);
+        for _ in 0..n {
+            result = self.step();
+            drew |= self.draw_flag;
+            if !matches!(result, Ok(StepResult::Continue)) {
+                break;
+            }
+        }
+        self.draw_flag |= drew;
+        result?.into_cycle_result()
+    }
+
+    fn run_cycle(&mut self) -> Result<u8, Chip8Error> {
+        if !self.running {
+            return Err(Chip8Error::Halted);
+        }
+
+        let skip_breakpoint = std::mem::take(&mut self.skip_breakpoint);
+        if !skip_breakpoint && self.breakpoints.contains(&self.pc) {
+            return Err(Chip8Error::BreakpointHit(self.pc));
+        }
+
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds(self.pc));
+        }
+
+        self.apply_input_script();
+
+        if self.profiling {
+            self.record_cycle_delta();
+        }
+
+        // Fetch
+        let fetch_start = self.profiling.then(Instant::now);
+        let inst_pc = self.pc;
+        let instruction: Instruction = self.fetch()?;
+        if let Some(start) = fetch_start {
+            self.timeline.fetch_us_total += start.elapsed().as_micros() as u64;
+        }
+        self.last_instruction = Some(instruction.instruction);
+        self.cycles += 1;
+        self.total_cycles += 1;
+        self.instruction_histogram[instruction.nibble as usize] += 1;
+        self.call_depth_histogram[self.sp.min(15)] += 1;
+        self.recent_pcs.rotate_left(1);
+        self.recent_pcs[3] = inst_pc;
+        
+        if self.debug && !self.fast_forward_mode {
+            print!("Instruction: {}\t", self.disassemble_instruction(&instruction));
+            self.debug_print();            
+        }
+
+        // Decode/Execute
+        let watched_before: Vec<Option<u8>> = self.watchpoints.iter().map(|watchpoint| self.watched_value(watchpoint)).collect();
+        self.register_watch_hit = None;
+        let raw = instruction.instruction;
+        let instruction_nibble = instruction.nibble;
+        let execute_start = self.profiling.then(Instant::now);
+        let result = self.execute(instruction);
+        if let Some(start) = execute_start {
+            self.timeline.execute_us_total += start.elapsed().as_micros() as u64;
+            self.timeline.cycles += 1;
+        }
+        self.max_observed_sp = self.max_observed_sp.max(self.sp);
+        self.record_trace(inst_pc, raw);
+
+        if self.debug && !self.fast_forward_mode {
+            self.paused = true;
+
+            if let Err(err) = self.check_fontset_execution() {
+                log::warn!("{}", err);
+            }
+        }
+
+        if instruction_nibble == 0xD && self.pause_on_frame == Some(self.frames_rendered) {
+            self.pause_on_frame = None;
+            self.paused = true;
+        }
+        if self.pause_on_cycle == Some(self.cycles) {
+            self.pause_on_cycle = None;
+            self.paused = true;
+        }
+
+        // Each jump back to the skip finishes another pass of a spin wait
+        if instruction_nibble == 0x1 && self.detect_spin_wait().is_some() {
+            self.spin_wait_count += 1;
+        }
+
+        let watchpoint = self.triggered_watchpoint(&watched_before);
+        if result.is_ok() && let Some(watchpoint) = watchpoint {
+            return Err(Chip8Error::WatchpointHit { watchpoint });
+        }
+        
+        result
+    }
+
+    // PC inside the font data usually means a call/jump to a font address instead of FX29
+    pub fn pc_calls_into_fontset(&self) -> Option<u16> {
+        Self::in_fontset(self.pc).then_some(self.pc)
+    }
+
+    pub fn sp_points_to_fontset(&self) -> bool {
+        self.stack[..self.sp].iter().any(|&addr| Self::in_fontset(addr))
+    }
+
+    pub fn check_fontset_execution(&self) -> Result<(), Chip8Error> {
+        if let Some(pc) = self.pc_calls_into_fontset() {
+            return Err(Chip8Error::FontsetExecution(pc));
+        }
+        if let Some(&addr) = self.stack[..self.sp].iter().find(|&&addr| Self::in_fontset(addr)) {
+            return Err(Chip8Error::FontsetExecution(addr));
+        }
+        Ok(())
+    }
+
+    fn in_fontset(addr: u16) -> bool {
+        (FONTSET_START..FONTSET_START + DEFAULT_FONTSET.len()).contains(&(addr as usize))
+    }
+
+    // Whether the last 4 instructions were the same skip-if-key and jump back, twice over
+    pub fn detect_spin_wait(&self) -> Option<SpinWaitInfo> {
+        let [a, b, c, d] = self.recent_pcs;
+        if a != c || b != d || b != a.wrapping_add(2) || self.cycles < 4 {
+            return None;
+        }
+
+        let read = |addr: u16| -> Option<u16> {
+            let high = *self.memory.get(addr as usize)?;
+            let low = *self.memory.get(addr as usize + 1)?;
+            Some((high as u16) << 8 | low as u16)
+        };
+        let skip = Instruction::new(read(a)?);
+        let jump = Instruction::new(read(b)?);
+
+        let is_key_skip = skip.nibble == 0xE && (skip.nn == 0x9E || skip.nn == 0xA1);
+        if is_key_skip && jump.nibble == 0x1 && jump.nnn == a {
+            return Some(SpinWaitInfo { loop_pc: a, key: self.v[skip.x] & 0xF });
+        }
+        None
+    }
+
+    pub fn spin_wait_count(&self) -> u64 {
+        self.spin_wait_count
+    }
+
+    // Turning profiling on starts a fresh timeline and opcode profile
+    pub fn enable_profiling(&mut self, enabled: bool) {
+        if enabled {
+            self.timeline = ExecutionTimeline::default();
+            self.last_cycle_at = None;
+            self.cycle_deltas.clear();
+            self.profile = Some(Box::default());
+        }
+        self.profiling = enabled;
+    }
+
+    // Stops collecting, what was collected stays readable until profiling is turned back on
+    pub fn disable_profiling(&mut self) {
+        self.enable_profiling(false);
+    }
+
+    pub fn get_profile(&self) -> Option<&ProfileData> {
+        self.profile.as_deref()
+    }
+
+    fn record_cycle_delta(&mut self) {
+        const MAX_SAMPLES: usize = 1000;
+
+        let now = Instant::now();
+        if let Some(last) = self.last_cycle_at {
+            if self.cycle_deltas.len() >= MAX_SAMPLES {
+                self.cycle_deltas.pop_front();
+            }
+            self.cycle_deltas.push_back((now - last).as_micros().min(u32::MAX as u128) as u32);
+        }
+        self.last_cycle_at = Some(now);
+    }
+
+    // Over the last 1000 cycles run while profiling
+    pub fn measure_cycle_jitter(&self) -> JitterStats {
+        let samples: Vec<u32> = self.cycle_deltas.iter().copied().collect();
+        JitterStats::from_samples(&samples)
+    }
+
+    pub fn execution_timeline(&self) -> ExecutionTimeline {
+        self.timeline
+    }
+
+    // For the parts of the game loop Chip8 can't time itself
+    pub fn record_profile(&mut self, phase: ProfilePhase, elapsed: Duration) {
+        if !self.profiling {
+            return;
+        }
+        let us = elapsed.as_micros() as u64;
+        match phase {
+            ProfilePhase::Display => self.timeline.display_us_total += us,
+            ProfilePhase::Sleep => self.timeline.sleep_us_total += us
+        }
+    }
+
+    // Instructions executed since reset, indexed by leading nibble
+    pub fn instruction_histogram(&self) -> &[u64; 16] {
+        &self.instruction_histogram
+    }
+
+    // Most executed groups first
+    pub fn instruction_histogram_named(&self) -> Vec<(String, u64)> {
+        let mut named: Vec<(String, u64)> = NIBBLE_NAMES.iter()
+            .zip(self.instruction_histogram.iter())
+            .map(|(name, &count)| (name.to_string(), count))
+            .collect();
+        named.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        named
+    }
+
+    pub fn most_used_opcode(&self) -> u8 {
+        // max_by_key returns the last maximum, reverse so ties go to the lowest nibble
+        self.instruction_histogram.iter().enumerate().rev()
+            .max_by_key(|&(_, count)| count)
+            .map(|(nibble, _)| nibble as u8)
+            .unwrap_or(0)
+    }
+
+    // Percentage of the built-in opcodes that have run at least once since reset
+    pub fn opcode_coverage(&self) -> f32 {
+        self.opcodes_executed.len() as f32 / OpcodeClass::ALL.len() as f32 * 100.0
+    }
+
+    pub fn uncovered_opcodes(&self) -> Vec<OpcodeClass> {
+        OpcodeClass::ALL.into_iter().filter(|class| !self.opcodes_executed.contains(class)).collect()
+    }
+
+    pub fn instruction_histogram_chart(&self) -> String {
+        const BAR_WIDTH: u64 = 50;
+        let max = self.instruction_histogram.iter().copied().max().unwrap_or(0).max(1);
+
+        let mut out = String::new();
+        for (name, &count) in NIBBLE_NAMES.iter().zip(self.instruction_histogram.iter()) {
+            let bar = "#".repeat((count * BAR_WIDTH / max) as usize);
+            out.push_str(&format!("{:<9} |{:<50}| {}\n", name, bar, count));
+        }
+        out
+    }
+
+    pub fn stack_frame_count(&self) -> usize {
+        self.sp
+    }
+
+    // Instructions executed at each call depth since reset, a full stack counts as depth 15
+    pub fn call_depth_histogram(&self) -> [u32; 16] {
+        self.call_depth_histogram
+    }
+
+    // Deepest the stack has been since reset
+    pub fn max_observed_sp(&self) -> usize {
+        self.max_observed_sp
+    }
+
+    // Depths past the deepest one reached are left off
+    pub fn call_depth_chart(&self) -> String {
+        const BAR_WIDTH: u32 = 50;
+        let max = self.call_depth_histogram.iter().copied().max().unwrap_or(0).max(1);
+
+        let mut out = String::new();
+        for (depth, &count) in self.call_depth_histogram.iter().enumerate().take(self.max_observed_sp.min(15) + 1) {
+            let bar = "#".repeat((count as u64 * BAR_WIDTH as u64 / max as u64) as usize);
+            out.push_str(&format!("Depth {:<3} |{:<50}| {}\n", depth, bar, count));
+        }
+        out
+    }
+
+    pub fn set_draw_hook(&mut self, hook: fn(&[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT])) {
+        self.draw_hook = Some(hook);
+    }
+
+    // Async version of the game loop for use inside a tokio runtime. Instructions are
+    // paced to target_ips and the timers tick from their own 60Hz interval. Never finishes
+    // while the ROM is running, cancel it with tokio::select! to stop early.
+    #[cfg(feature = "tokio")]
+    pub async fn run_cycles_timed(&mut self, target_ips: f64) {
+        use tokio::time::{interval, sleep_until, Instant as TokioInstant};
+
+        let period = Duration::from_secs_f64(1.0 / target_ips);
+        let mut timers = interval(self.timer_interval);
+        let mut next_cycle = TokioInstant::now();
+
+        loop {
+            tokio::select! {
+                _ = timers.tick() => self.tick_timers(),
+                _ = sleep_until(next_cycle) => {
+                    match self.cycle() {
+                        Ok(SUCCESSFUL_EXECUTION) => {}
+                        _ => return // ROM exited, halted or failed
+                    }
+
+                    if self.display_changed {
+                        if let Some(hook) = self.draw_hook {
+                            hook(&self.display);
                         }
+                        self.mark_rendered();
                     }
-                    _ => { panic!("Unknown opcode: {:04X}", inst.instruction); }
+
+                    // Measured from the schedule, so execution time comes out of the sleep
+                    next_cycle += period;
                 }
             }
-            0x6 => {
-                // SET: VX = NN
-                self.v[inst.x] = inst.nn;
+        }
+    }
+
+    pub fn cycles_since_reset(&self) -> u64 {
+        self.cycles
+    }
+
+    // Every cycle run since power on, resets included
+    pub fn cycle_count(&self) -> u64 {
+        self.total_cycles
+    }
+
+    // Runs flat out until the cycle counter reaches target_cycle (or the ROM exits).
+    // No keys are held down while fast forwarding, so key waits just keep waiting.
+    pub fn fast_forward(&mut self, target_cycle: u64) -> Result<(), Chip8Error> {
+        let keypad = self.keypad;
+        self.keypad = [false; 16];
+        self.fast_forward_mode = true;
+
+        let mut result = Ok(());
+        while self.cycles < target_cycle {
+            match self.cycle() {
+                Ok(EXIT_ROM) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
             }
-            0x7 => {
-                // ADD: VX = VX + NN
-                let (result, _) = self.v[inst.x].overflowing_add(inst.nn);
-                self.v[inst.x] = result;
+        }
+
+        self.fast_forward_mode = false;
+        self.keypad = keypad;
+        result
+    }
+
+    // The bytes raw can write at the current I, which are the only ones worth diffing.
+    // FX33 writes 3, FX55 V0-VX and 5XY2 VX-VY, nothing else touches memory.
+    fn memory_written_by(&self, raw: u16) -> std::ops::Range<usize> {
+        let len = match Self::decode_only(raw) {
+            Ok(Opcode::Bcd { .. }) => 3,
+            Ok(Opcode::Store { x }) => x + 1,
+            Ok(Opcode::StoreRange { x, y }) => (y + 1).saturating_sub(x),
+            _ => 0
+        };
+        let start = (self.i as usize).min(self.memory.len());
+        start..(start + len).min(self.memory.len())
+    }
+
+    // Runs up to max cycles on a throwaway copy and reports what each instruction did,
+    // stopping early if the ROM exits or hits an error. self is never touched.
+    pub fn execute_all_from_pc(&self, max: usize) -> Vec<ExecutionRecord> {
+        let mut sim = self.detached_copy();
+        let mut records: Vec<ExecutionRecord> = Vec::new();
+
+        for _ in 0..max {
+            if sim.pc as usize + 1 >= sim.memory.len() {
+                break;
             }
-            0x8 => {
-                match inst.n {
-                    // BINARY OPS
-                    0x0 => {
-                        // SET: VX = VY
-                        self.v[inst.x] = self.v[inst.y];
-                    }
-                    0x1 => {
-                        // OR: VX = VX OR VY
-                        self.v[inst.x] = self.v[inst.x] | self.v[inst.y];
-                        if self.quirks.vf_reset {
-                            self.v[0xF] = 0; 
-                        }
-                    }
-                    0x2 => {
-                        // AND: VX = VX AND VY
-                        self.v[inst.x] = self.v[inst.x] & self.v[inst.y];
-                        if self.quirks.vf_reset {
-                            self.v[0xF] = 0; 
-                        }
-                    }
-                    0x3 => {
-                        // XOR: VX = VX XOR VY
-                        self.v[inst.x] = self.v[inst.x] ^ self.v[inst.y];
-                        if self.quirks.vf_reset {
-                            self.v[0xF] = 0; 
-                        }
-                    }
-                    0x4 => {
-                        // ADD (with overflow): VX = VX + VY
-                        let (sum, carry) = self.v[inst.x].overflowing_add(self.v[inst.y]);
-                        self.v[inst.x] = sum;
-                        self.v[0xF] = if carry { 1 } else { 0 };
-                    }
-                    0x5 => {
-                        // 8XY5 sets VX to the result of VX - VY.                        
-                        let (result, borrowed) = self.v[inst.x].overflowing_sub(self.v[inst.y]);
-                        self.v[inst.x] = result;
-                        self.v[0xF] = if borrowed { 0 } else { 1 };
-                    }
-                    0x6 => {
-                        // SHIFT QUIRK: 8XY6 VX = VY >> 1
-                        // No QUIRK:    8XY6 VX = VX >> 1
-                        let shift_src = if self.quirks.shift { inst.x } else { inst.y };
-                        let lsb: u8 = self.v[shift_src] & 0x1;
-                        self.v[inst.x] = self.v[shift_src] >> 1;
-                        self.v[0xF] = lsb;
-                    }
-                    0x7 => {
-                        // 8XY7 sets VX to the result of VY - VX.
-                        let (result, borrowed) = self.v[inst.y].overflowing_sub(self.v[inst.x]);
-                        self.v[inst.x] = result;
-                        self.v[0xF] = if borrowed { 0 } else { 1 };
-                    }
-                    0xE => {
-                        // YSHIFT:    8XYE VX = VY << 1
-                        // No YSHIFT: 8XYE VX = VX << 1
-                        let shift_src = if self.quirks.shift { inst.x } else { inst.y };
-                        let msb: u8 = (self.v[shift_src] >> 7) & 0x1;
-                        self.v[inst.x] = self.v[shift_src] << 1;
-                        self.v[0xF] = msb;
-                    }
-                    _ => { panic!("Unknown opcode: {:04X}", inst.instruction); }
+
+            let pc = sim.pc;
+            let raw = (sim.memory[pc as usize] as u16) << 8 | sim.memory[pc as usize + 1] as u16;
+            let before = (sim.v, sim.i, sim.sp, sim.delay_timer, sim.sound_timer, sim.display);
+            let written = sim.memory_written_by(raw);
+            let memory_before = sim.memory[written.clone()].to_vec();
+
+            let result = sim.cycle();
+
+            let mnemonic = Self::trace_mnemonic(raw);
+
+            let mut changed_fields: Vec<StateChange> = Vec::new();
+            for (reg, (&old, &new)) in before.0.iter().zip(sim.v.iter()).enumerate() {
+                if old != new {
+                    changed_fields.push(StateChange::Register { reg: reg as u8, old, new });
                 }
             }
-            0x9 => {
-                match inst.n {
-                    0x0 => {
-                        // Skip 1 instruction if VX != VY
-                        if self.v[inst.x] != self.v[inst.y] {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => { panic!("Unknown opcode: {:04X}", inst.instruction) }
+            if before.1 != sim.i {
+                changed_fields.push(StateChange::Index { old: before.1, new: sim.i });
+            }
+            if before.2 != sim.sp {
+                changed_fields.push(StateChange::StackPointer { old: before.2 as u8, new: sim.sp as u8 });
+            }
+            for ((addr, &old), &new) in written.clone().zip(memory_before.iter()).zip(sim.memory[written].iter()) {
+                if old != new {
+                    changed_fields.push(StateChange::Memory { addr: addr as u16, old, new });
                 }
             }
-            0xA => {
-                // I = NNN
-                self.i = inst.nnn;
+            if before.3 != sim.delay_timer {
+                changed_fields.push(StateChange::DelayTimer { old: before.3, new: sim.delay_timer });
             }
-            0xB => {
-                // CHIP-8:     0xBNNN Jump to NNN + V0
-                // SUPER-CHIP: 0xBXNN Jump to XNN + VX
-                let v_src = if self.quirks.jump { inst.x } else { 0 };
-                self.pc = inst.nnn + self.v[v_src] as u16;
+            if before.4 != sim.sound_timer {
+                changed_fields.push(StateChange::SoundTimer { old: before.4, new: sim.sound_timer });
             }
-            0xC => {
-                // VX = random number bitwise & with NN
-                let random_byte: u8 = (self.rng.next_u32() & 0xFF) as u8;
-                self.v[inst.x] = random_byte & inst.nn;
+            let pixels_changed = sim.display_diff(&before.5);
+            if pixels_changed > 0 {
+                changed_fields.push(StateChange::Display { pixels_changed });
             }
-            0xD => {
-                // Alter Display
-                let x_coord = self.v[inst.x] as usize % DISPLAY_WIDTH;
-                let y_coord = self.v[inst.y] as usize % DISPLAY_HEIGHT;
-                self.v[0xF] = 0; // Reset collision flag
 
-                for index in 0..inst.n as usize {
-                    let sprite_byte = self.memory[self.i as usize + index];
+            records.push(ExecutionRecord { pc, raw, mnemonic, changed_fields });
 
-                    // Y-coordinate handling
-                    let pixel_y = y_coord + index;
-                    if self.quirks.clip && pixel_y >= DISPLAY_HEIGHT {
-                        continue; // skip drawing if clipped vertically
-                    }
+            if !matches!(result, Ok(SUCCESSFUL_EXECUTION)) {
+                break;
+            }
+        }
 
-                    for bit_index in 0..8 {
-                        let pixel_x = x_coord + bit_index;
-                        if self.quirks.clip && pixel_x >= DISPLAY_WIDTH {
-                            continue; // skip drawing if clipped horizontally
-                        }
+        records
+    }
 
-                        // Apply wrapping if clipping is off
-                        let px = if self.quirks.clip {
-                            pixel_x
-                        } else {
-                            pixel_x % DISPLAY_WIDTH
-                        };
-                        let py = if self.quirks.clip {
-                            pixel_y
-                        } else {
-                            pixel_y % DISPLAY_HEIGHT
-                        };
+    // Runs the loaded program while counting a 60 tick delay timer down, the same way
+    // the game loop does, and compares how long that took against the expected second.
+    // CPU state is reset afterwards, memory is left alone.
+    pub fn cycle_accurate_delay_test(&mut self) -> Result<DelayTestResult, Chip8Error> {
+        const TEST_TICKS: u8 = 60;
+        const CYCLES_PER_FRAME: u32 = 10;
+        let expected = TIMER_INTERVAL * TEST_TICKS as u32;
 
-                        let sprite_pixel_on = (sprite_byte >> (7 - bit_index)) & 1 == 1;
-                        let current_pixel = self.display[py][px];
+        self.reset();
+        self.delay_timer = TEST_TICKS;
 
-                        if sprite_pixel_on {
-                            if current_pixel {
-                                self.v[0xF] = 1; // Collision
-                            }
+        let start = Instant::now();
+        let mut last_tick = start;
+        let mut tick_count: u32 = 0;
+        let mut exited = false;
 
-                            self.display[py][px] ^= true;
-                        }
-                    }
+        // Give up if the program keeps reloading the delay timer
+        while self.delay_timer > 0 && start.elapsed() < expected * 10 {
+            for _ in 0..CYCLES_PER_FRAME {
+                if exited {
+                    break;
                 }
-                self.draw_flag = true;
+                exited = self.cycle()? == EXIT_ROM;
             }
-            0xE => {
-                match inst.nn {
-                    0x9E => {
-                        // Skip next instruction if X key is pressed
-                        if self.keypad[self.v[inst.x] as usize] {
-                            self.pc += 2;
-                        }
-                    }
-                    0xA1 => {
-                        // Skip next instruction if X key is NOT pressed
-                        if !self.keypad[self.v[inst.x] as usize] {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => { panic!("Unknown opcode: {:04X}", inst.instruction) }
+
+            let now = Instant::now();
+            let before = self.delay_timer;
+            self.tick_timers_with_debt(now - last_tick);
+            tick_count += before.saturating_sub(self.delay_timer) as u32;
+            last_tick = now;
+        }
+
+        let expected_ms = expected.as_millis() as u64;
+        let actual_ms = start.elapsed().as_millis() as u64;
+        let drift_pct = (actual_ms as f64 - expected_ms as f64) / expected_ms as f64 * 100.0;
+
+        self.reset();
+
+        Ok(DelayTestResult { expected_ms, actual_ms, tick_count, drift_pct })
+    }
+
+    pub fn set_target_ips(&mut self, target_ips: u64) {
+        self.target_ips = target_ips.max(1);
+    }
+
+    // One frame's worth of cycles at target_ips followed by a single timer tick. Stops early
+    // if the ROM exits or fails. Render the display afterwards if drew is set.
+    pub fn step_one_frame(&mut self, fps: u64) -> FrameResult {
+        let cycles_per_frame = (self.target_ips / fps.max(1)).max(1);
+        let mut result = FrameResult { cycles_run: 0, drew: false, sound_active: false, exit_code: None, error: None };
+
+        for _ in 0..cycles_per_frame {
+            match self.cycle() {
+                Ok(EXIT_ROM) => {
+                    result.exit_code = Some(self.v[1]);
+                    break;
                 }
-            }
-            0xF => {
-                match inst.nn {
-                    // Timer Instructions
-                    0x07 => {
-                        // Set VX to current value of Delay Timer
-                        self.v[inst.x] = self.delay_timer;
-                    }
-                    0x0A => {
-                        if !self.wait_for_release { // If not actively waiting for key release
-                            // Check if any button is pressed
-                            for (i, &pressed) in self.keypad.iter().enumerate() {
-                                if pressed {
-                                    self.wait_for_release = true;
-                                    self.wait_key = i;
-                                    break;
-                                }
-                            }
-                        } 
-                        // If waiting for initial key press or key release
-                        if !self.wait_for_release || (self.wait_for_release && self.keypad[self.wait_key]) {
-                            // Don't advance to next instruction
-                            self.pc -= 2;
-                        } else { // The key was let go
-                            self.v[inst.x] = self.wait_key as u8;
-                            self.wait_for_release = false;
-                        }
-                    }
-                    0x15 => {
-                        // Sets the Delay Timer to the value in VX
-                        self.delay_timer = self.v[inst.x];
-                    }
-                    0x18 => {
-                        // Sets the sound timer to the value in VX
-                        self.sound_timer = self.v[inst.x];
-                    }
-                    0x1E => {
-                        // I = I + VX
-                        let (result, overflow) = self.i.overflowing_add(self.v[inst.x] as u16);
-                        self.i = result;
-                        self.v[0xF] = if overflow { 1 } else { 0 };
-                    }
-                    0x29 => {
-                        // I = location of sprite for digit in VX
-                        self.i = FONTSET_START as u16 + (self.v[inst.x] as u16 * 5);
-                    }
-                    0x33 => {
-                        // Store number in VX as three decimal digits, and stores in mem at location in reg I
-                        let value = self.v[inst.x];
-                        self.memory[self.i as usize] = value / 100;
-                        self.memory[self.i as usize + 1] = (value % 100) / 10;
-                        self.memory[self.i as usize + 2] = value % 10;
-                    }
-                    0x55 => {
-                        // Store V0-VX variables in memory
-                        for step in 0..=inst.x {
-                            self.memory[self.i as usize + step] = self.v[step];
-                        }
-                        // Original Chip-8 incremented I, but modern don't update I
-                        if self.quirks.load_store {
-                            self.i += inst.x as u16 + 1;
-                        }
-                    }
-                    0x65 => {
-                        // Loads from memory variables into V0-VX
-                        for step in 0..=inst.x {
-                            self.v[step] = self.memory[self.i as usize + step];
-                        }
-                        // Original Chip-8 incremented I, but modern don't update I
-                        if self.quirks.load_store {
-                            self.i += inst.x as u16 + 1;
-                        }
-                    }
-                    _ => { println!("Unknown opcode: {:04X}", inst.instruction); }
+                Ok(_) => result.cycles_run += 1,
+                Err(err) => {
+                    result.error = Some(err);
+                    break;
                 }
             }
-            _ => {
-                panic!("IMPOSSIBLE NIBBLE! {}", inst.instruction)
-            }
         }
-        Ok(SUCCESSFUL_EXECUTION)        
+        self.tick_timers();
+
+        result.drew = self.draw_flag;
+        result.sound_active = self.sound_timer > 0;
+        result
     }
 
-    pub fn cycle(&mut self) -> std::io::Result<u8> {
-        // Fetch
-        let instruction: Instruction = self.fetch();
-        
-        if self.debug {
-            print!("Instruction: 0x{:04X}\t", instruction.instruction);
-            self.debug_print();            
+    // Headless run_game_loop: cycles_per_frame at a time with the timers ticking at timer_hz
+    // off the wall clock, until duration is up or the ROM exits. Runs flat out, fps and
+    // idle_timeout are ignored. Keys come from self.keypad or an input script.
+    // Returns how many cycles ran.
+    pub fn run_for_duration(&mut self, duration: Duration, config: &EmulatorConfig) -> Result<u64, Chip8Error> {
+        self.set_timer_hz(config.timer_hz);
+        let start = Instant::now();
+        let mut last_tick = start;
+        let mut cycles_run: u64 = 0;
+
+        while start.elapsed() < duration {
+            let before = self.total_cycles;
+            let result = self.cycle_n(config.cycles_per_frame.max(1));
+            cycles_run += self.total_cycles - before;
+            if result? == EXIT_ROM {
+                break;
+            }
+
+            let now = Instant::now();
+            self.tick_timers_with_debt(now - last_tick);
+            last_tick = now;
         }
 
-        // Decode/Execute
-        let result = self.execute(instruction);
+        Ok(cycles_run)
+    }
 
-        if self.debug {
-            self.paused = true;
+    pub fn cycle_with_context(&mut self, ctx: &mut CycleContext) -> Result<CycleResult, Chip8Error> {
+        let cycles_before = self.total_cycles;
+        let sound_before = self.sound_timer > 0;
+
+        // Track the draw flag for this cycle alone, then restore anything left over
+        let draw_before = self.draw_flag;
+        self.draw_flag = false;
+
+        let step = self.step()?;
+
+        let drew = self.draw_flag;
+        self.draw_flag |= draw_before;
+        if drew {
+            ctx.frame_parity ^= 1;
         }
-        
-        result
+
+        // Skips and FX0A's wait move the PC too, only jumps, calls and returns count. A
+        // breakpoint or halt runs nothing at all.
+        let ran = self.total_cycles != cycles_before;
+        let jumped = ran && step != StepResult::ExitRom && self.last_instruction.is_some_and(|raw| {
+            matches!(Self::decode_only(raw), Ok(Opcode::Jp(_) | Opcode::JpOffset { .. } | Opcode::Call(_) | Opcode::Ret))
+        });
+
+        Ok(CycleResult {
+            drew,
+            sound_changed: sound_before != (self.sound_timer > 0),
+            jumped,
+            exited: step == StepResult::ExitRom,
+            step
+        })
+    }
+}
+
+// Serde support goes through the save state format, so the two can't drift apart. Memory
+// size isn't part of a save state, so it travels alongside it.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedChip8 {
+    memory_size: usize,
+    state: Vec<u8>
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Chip8 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedChip8 { memory_size: self.memory.len(), state: self.save_state() }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Chip8 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let serialized = SerializedChip8::deserialize(deserializer)?;
+        let mut chip8 = Chip8::with_memory_size(serialized.memory_size)
+            .build()
+            .map_err(D::Error::custom)?;
+        chip8.load_state(&serialized.state).map_err(D::Error::custom)?;
+        Ok(chip8)
     }
 }
@@ -0,0 +1,111 @@
+use crate::emulator::EmulatorConfig;
+use crate::error::Chip8Error;
+use crate::quirks::{Platform, Quirks};
+
+// One [section] of the ROM list
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomConfig {
+    pub id: String,
+    pub file: String,
+    pub name: String,
+    // Overrides for the EmulatorConfig the ROM runs with, None keeps what main.rs picked
+    pub fps: Option<u64>,
+    pub cycles_per_frame: Option<u32>,
+    pub timer_hz: Option<u64>,
+    pub quirks: Quirks,
+    pub platform: Platform
+}
+
+impl RomConfig {
+    fn new(id: &str) -> Self {
+        RomConfig {
+            id: id.to_string(),
+            file: String::new(),
+            name: id.to_uppercase(),
+            fps: None,
+            cycles_per_frame: None,
+            timer_hz: None,
+            quirks: Quirks::chip8(),
+            platform: Platform::Chip8
+        }
+    }
+
+    pub fn emulator_config(&self, base: &EmulatorConfig) -> EmulatorConfig {
+        EmulatorConfig {
+            fps: self.fps.unwrap_or(base.fps),
+            cycles_per_frame: self.cycles_per_frame.unwrap_or(base.cycles_per_frame),
+            timer_hz: self.timer_hz.unwrap_or(base.timer_hz),
+            ..*base
+        }
+    }
+}
+
+pub fn load_rom_list(path: &str) -> Result<Vec<RomConfig>, Chip8Error> {
+    parse_rom_list(&std::fs::read_to_string(path)?)
+}
+
+// The small part of TOML the ROM list needs: [sections], key = "string" or key = integer,
+// and whole line # comments
+pub fn parse_rom_list(text: &str) -> Result<Vec<RomConfig>, Chip8Error> {
+    let mut roms: Vec<RomConfig> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(id) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            check_has_file(roms.last())?;
+            roms.push(RomConfig::new(id.trim()));
+            continue;
+        }
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| config_error(line_no, "expected [section] or key = value"))?;
+        let (key, value) = (key.trim(), value.trim());
+        let rom = roms.last_mut().ok_or_else(|| config_error(line_no, "key outside of a [section]"))?;
+
+        match key {
+            "file" => rom.file = parse_string(value, line_no)?,
+            "name" => rom.name = parse_string(value, line_no)?,
+            "fps" => rom.fps = Some(parse_number(value, line_no)?),
+            "cycles_per_frame" => rom.cycles_per_frame = Some(parse_number(value, line_no)?),
+            "timer_hz" => rom.timer_hz = Some(parse_number(value, line_no)?),
+            "quirks" => {
+                let preset = parse_string(value, line_no)?;
+                rom.quirks = Quirks::from_preset_name(&preset)
+                    .ok_or_else(|| config_error(line_no, &format!("unknown quirks preset {:?}", preset)))?;
+                // Every quirks preset has a platform, so this can't fail after the line above
+                rom.platform = Platform::from_preset_name(&preset).unwrap_or(Platform::Chip8);
+            }
+            _ => return Err(config_error(line_no, &format!("unknown key {:?}", key)))
+        }
+    }
+
+    check_has_file(roms.last())?;
+    Ok(roms)
+}
+
+fn check_has_file(rom: Option<&RomConfig>) -> Result<(), Chip8Error> {
+    match rom {
+        Some(rom) if rom.file.is_empty() => Err(Chip8Error::InvalidConfig(format!("[{}] has no file", rom.id))),
+        _ => Ok(())
+    }
+}
+
+fn parse_string(value: &str, line_no: usize) -> Result<String, Chip8Error> {
+    value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+        .map(str::to_string)
+        .ok_or_else(|| config_error(line_no, "expected a quoted string"))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, line_no: usize) -> Result<T, Chip8Error> {
+    value.parse().map_err(|_| config_error(line_no, &format!("{:?} isn't a valid number", value)))
+}
+
+fn config_error(line_no: usize, msg: &str) -> Chip8Error {
+    Chip8Error::InvalidConfig(format!("line {}: {}", line_no, msg))
+}
@@ -1,5 +1,6 @@
+use rppal::gpio::{InputPin, Level};
 use rppal::spi::Spi;
-use std::{thread, time::Duration};
+use std::{fmt, thread, time::{Duration, Instant}};
 
 const NUM_PAGES: u8 = 8;
 
@@ -7,11 +8,33 @@ const PAGE_ADDRESS_START: u8 = 0xB0;
 const DISPLAY_OFF: u8 = 0xAE;
 const DISPLAY_ON: u8 = 0xAF;
 const VERT_START_MASK: u8 = 0x3F;
+const LOWER_COLUMN_START: u8 = 0x00;
+const UPPER_COLUMN_START: u8 = 0x10;
 
 const SSD1309_WIDTH: usize = 128;
 
+// Orientation commands, segment remap mirrors left/right and COM scan direction top/bottom
+const SEG_REMAP_OFF: u8 = 0xA0;
+const SEG_REMAP_ON: u8 = 0xA1;
+const COM_SCAN_INC: u8 = 0xC0;
+const COM_SCAN_DEC: u8 = 0xC8;
+
+const SET_CONTRAST: u8 = 0x81;
+const DEFAULT_CONTRAST: u8 = 0xCF;
+const NORMAL_DISPLAY: u8 = 0xA6;
+const INVERSE_DISPLAY: u8 = 0xA7;
+
+// Timing settings sent during initialize, also used to estimate the scan period
+const CLOCK_DIVIDE: u8 = 0x80;
+const MULTIPLEX_RATIO: u8 = 0x3F;
+const PRECHARGE_PERIOD: u8 = 0xF1;
+
+// Internal oscillator frequency at the 0x8 setting in CLOCK_DIVIDE, going by the datasheet
+const OSC_FREQ_HZ: u64 = 370_000;
+
 const SOURCE_WIDTH: usize = 64;
 const SOURCE_HEIGHT: usize = 32;
+const HIRES_SOURCE_HEIGHT: usize = 64;
 
 // ==== SSD1309 Normal Commands (DC = 0) ==== From https://www.hpinfotech.ro/SSD1309.pdf at roughly page 27
 // 0xA5 => Entire Display on (ignore ram)
@@ -33,45 +56,266 @@ const SOURCE_HEIGHT: usize = 32;
 // 0x8D => Charge pump (must set this!)
 
 // ==== Scrolling Commands ====
-// 0x26 => Right Horizontal Scroll
+// 0x26 => Right Horizontal Scroll (0x27 for left)
 // 0x00 => Dummy byte (always 0x00)
 // 0x00 => Start page address (0 to 7)
 // 0x07 => Scroll interval (time between steps)
 // 0x07 => End page address (0 to 7)
 // 0x00 => Dummy byte (always 0x00)
-// 0xFF => Dummy byte (always 0xFF)
+// 0x00 => Start column (0 to 127)
+// 0x7F => End column (0 to 127)
 // 0x2F => Activate Scroll
 // 0x2E => Deactivate scroll
+const SCROLL_RIGHT: u8 = 0x26;
+const SCROLL_LEFT: u8 = 0x27;
+const ACTIVATE_SCROLL: u8 = 0x2F;
+const DEACTIVATE_SCROLL: u8 = 0x2E;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollDirection {
+    Left,
+    Right
+}
+
+// Frames between each one column scroll step, the values are the SSD1309's encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollInterval {
+    Frames2 = 0x07,
+    Frames3 = 0x04,
+    Frames4 = 0x05,
+    Frames5 = 0x00,
+    Frames25 = 0x06,
+    Frames64 = 0x01,
+    Frames128 = 0x02,
+    Frames256 = 0x03
+}
+
+// 5x7 text font, one byte per column with bit 0 at the top
+const TEXT_GLYPH_WIDTH: usize = 5;
+
+fn text_glyph(ch: char) -> [u8; TEXT_GLYPH_WIDTH] {
+    match ch.to_ascii_uppercase() {
+        '0' => [0x3E, 0x51, 0x49, 0x45, 0x3E],
+        '1' => [0x00, 0x42, 0x7F, 0x40, 0x00],
+        '2' => [0x42, 0x61, 0x51, 0x49, 0x46],
+        '3' => [0x21, 0x41, 0x45, 0x4B, 0x31],
+        '4' => [0x18, 0x14, 0x12, 0x7F, 0x10],
+        '5' => [0x27, 0x45, 0x45, 0x45, 0x39],
+        '6' => [0x3C, 0x4A, 0x49, 0x49, 0x30],
+        '7' => [0x01, 0x71, 0x09, 0x05, 0x03],
+        '8' => [0x36, 0x49, 0x49, 0x49, 0x36],
+        '9' => [0x06, 0x49, 0x49, 0x29, 0x1E],
+        'A' => [0x7E, 0x11, 0x11, 0x11, 0x7E],
+        'B' => [0x7F, 0x49, 0x49, 0x49, 0x36],
+        'C' => [0x3E, 0x41, 0x41, 0x41, 0x22],
+        'D' => [0x7F, 0x41, 0x41, 0x22, 0x1C],
+        'E' => [0x7F, 0x49, 0x49, 0x49, 0x41],
+        'F' => [0x7F, 0x09, 0x09, 0x01, 0x01],
+        'G' => [0x3E, 0x41, 0x49, 0x49, 0x7A],
+        'H' => [0x7F, 0x08, 0x08, 0x08, 0x7F],
+        'I' => [0x00, 0x41, 0x7F, 0x41, 0x00],
+        'J' => [0x20, 0x40, 0x41, 0x3F, 0x01],
+        'K' => [0x7F, 0x08, 0x14, 0x22, 0x41],
+        'L' => [0x7F, 0x40, 0x40, 0x40, 0x40],
+        'M' => [0x7F, 0x02, 0x0C, 0x02, 0x7F],
+        'N' => [0x7F, 0x04, 0x08, 0x10, 0x7F],
+        'O' => [0x3E, 0x41, 0x41, 0x41, 0x3E],
+        'P' => [0x7F, 0x09, 0x09, 0x09, 0x06],
+        'Q' => [0x3E, 0x41, 0x51, 0x21, 0x5E],
+        'R' => [0x7F, 0x09, 0x19, 0x29, 0x46],
+        'S' => [0x46, 0x49, 0x49, 0x49, 0x31],
+        'T' => [0x01, 0x01, 0x7F, 0x01, 0x01],
+        'U' => [0x3F, 0x40, 0x40, 0x40, 0x3F],
+        'V' => [0x1F, 0x20, 0x40, 0x20, 0x1F],
+        'W' => [0x3F, 0x40, 0x38, 0x40, 0x3F],
+        'X' => [0x63, 0x14, 0x08, 0x14, 0x63],
+        'Y' => [0x07, 0x08, 0x70, 0x08, 0x07],
+        'Z' => [0x61, 0x51, 0x49, 0x45, 0x43],
+        ':' => [0x00, 0x36, 0x36, 0x00, 0x00],
+        '-' => [0x08, 0x08, 0x08, 0x08, 0x08],
+        '.' => [0x00, 0x60, 0x60, 0x00, 0x00],
+        '!' => [0x00, 0x00, 0x5F, 0x00, 0x00],
+        '/' => [0x20, 0x10, 0x08, 0x04, 0x02],
+        _ => [0x00; TEXT_GLYPH_WIDTH] // Unsupported characters render as spaces
+    }
+}
+
+// XO-CHIP's two planes at panel resolution. Anything on plane 0 is solid, pixels only on
+// plane 1 get a checkerboard across their 2x2 block so they come out at half brightness.
+fn dither_planes(p0: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT], p1: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) -> [[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT] {
+    let mut out = [[false; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT];
+    for (y, row) in out.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let (src_x, src_y) = (x / 2, y / 2);
+            *pixel = p0[src_y][src_x] || (p1[src_y][src_x] && (x + y) % 2 == 0);
+        }
+    }
+    out
+}
+
+// What the emulator needs from a screen, so frames can go somewhere other than the SSD1309
+pub trait ChipDisplay {
+    fn initialize(&mut self);
+    fn clear(&mut self);
+    fn display_2d_array(&mut self, array: [[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]);
+    fn display_hires_array(&mut self, array: &[[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT]);
+    fn display_planes(&mut self, p0: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT], p1: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) {
+        self.display_hires_array(&dither_planes(p0, p1));
+    }
+    fn turn_on(&mut self);
+    fn turn_off(&mut self);
+}
+
+// Keeps the last frame it was sent, for checking output without the hardware
+pub struct MockDisplay {
+    pub last_frame: Option<[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]>,
+    pub last_hires_frame: Option<[[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT]>,
+    pub frames_shown: u64,
+    pub on: bool
+}
+
+impl Default for MockDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockDisplay {
+    pub fn new() -> Self {
+        MockDisplay { last_frame: None, last_hires_frame: None, frames_shown: 0, on: false }
+    }
+}
+
+impl ChipDisplay for MockDisplay {
+    fn initialize(&mut self) {
+        self.on = true;
+    }
+
+    fn clear(&mut self) {
+        self.last_frame = None;
+        self.last_hires_frame = None;
+    }
+
+    fn display_2d_array(&mut self, array: [[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) {
+        self.last_frame = Some(array);
+        self.last_hires_frame = None;
+        self.frames_shown += 1;
+    }
+
+    fn display_hires_array(&mut self, array: &[[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT]) {
+        self.last_hires_frame = Some(*array);
+        self.last_frame = None;
+        self.frames_shown += 1;
+    }
+
+    fn turn_on(&mut self) {
+        self.on = true;
+    }
+
+    fn turn_off(&mut self) {
+        self.on = false;
+    }
+}
+
+// How the panel is mounted, Normal being the way up my board has it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayOrientation {
+    #[default]
+    Normal,
+    FlippedH,
+    FlippedV,
+    Rotated180
+}
+
+impl DisplayOrientation {
+    fn commands(&self) -> [u8; 2] {
+        match self {
+            DisplayOrientation::Normal => [SEG_REMAP_ON, COM_SCAN_DEC],
+            DisplayOrientation::FlippedH => [SEG_REMAP_OFF, COM_SCAN_DEC],
+            DisplayOrientation::FlippedV => [SEG_REMAP_ON, COM_SCAN_INC],
+            DisplayOrientation::Rotated180 => [SEG_REMAP_OFF, COM_SCAN_INC]
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DisplayError {
+    RegionOutOfBounds,
+    BufferSizeMismatch
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayError::RegionOutOfBounds => write!(f, "Region is outside the display"),
+            DisplayError::BufferSizeMismatch => write!(f, "Buffer size doesn't match the region")
+        }
+    }
+}
+
+impl std::error::Error for DisplayError {}
+
+// Page and column address commands for one page, followed by the data bytes written there
+pub type PageWrite = ([u8; 3], Vec<u8>);
 
 pub struct DisplayInterface {
     spi: Spi,
     dc: rppal::gpio::OutputPin,
-    rst: rppal::gpio::OutputPin
+    rst: rppal::gpio::OutputPin,
+
+    // Copy of what's been written to the display RAM, along with where the next
+    // data byte lands (page, column), following the page/column address commands
+    shadow: [[u8; SSD1309_WIDTH]; NUM_PAGES as usize],
+    cursor: (usize, usize),
+
+    // Optional input wired to the panel's frame sync output, scans are
+    // estimated from when the display was initialized without it
+    sync_pin: Option<InputPin>,
+    scan_start: Instant,
+
+    contrast: u8,
+    inverted: bool,
+    scrolling: bool,
+    orientation: DisplayOrientation
 }
 
 impl DisplayInterface {
-    pub fn new(spi: Spi, dc: rppal::gpio::OutputPin, rst: rppal::gpio::OutputPin) -> DisplayInterface {
-        Self { spi, dc, rst }
+    pub fn new(spi: Spi, dc: rppal::gpio::OutputPin, rst: rppal::gpio::OutputPin, orientation: DisplayOrientation) -> DisplayInterface {
+        Self {
+            spi,
+            dc,
+            rst,
+            shadow: [[0; SSD1309_WIDTH]; NUM_PAGES as usize],
+            cursor: (0, 0),
+            sync_pin: None,
+            scan_start: Instant::now(),
+            contrast: DEFAULT_CONTRAST,
+            inverted: false,
+            scrolling: false,
+            orientation
+        }
     }
 
     pub fn initialize(&mut self) {
+        let [seg_remap, com_scan] = self.orientation.commands();
+
         // SSD1309 init sequence
         let init_cmds = [
             DISPLAY_OFF,    // Display OFF
-            0xD5, 0x80,     // Clock divide
-            0xA8, 0x3F,     // Multiplex: 64
+            0xD5, CLOCK_DIVIDE,     // Clock divide
+            0xA8, MULTIPLEX_RATIO,  // Multiplex: 64
             0xD3, 0x00,     // Display offset
             0x40,           // Start line
             0x8D, 0x14,     // Charge pump ON
             0x20, 0x00,     // Memory mode: horizontal
-            0xA1,           // Seg remap
-            0xC8,           // COM scan dec
+            seg_remap,      // Seg remap
+            com_scan,       // COM scan direction
             0xDA, 0x12,     // COM pins
-            0x81, 0xCF,     // Contrast
-            0xD9, 0xF1,     // Precharge
+            SET_CONTRAST, self.contrast, // Contrast
+            0xD9, PRECHARGE_PERIOD, // Precharge
             0xDB, 0x40,     // VCOM detect
             0xA4,           // Resume from RAM
-            0xA6,           // Normal display
+            if self.inverted { INVERSE_DISPLAY } else { NORMAL_DISPLAY },
             DISPLAY_ON      // Display ON
         ];
         
@@ -85,16 +329,121 @@ impl DisplayInterface {
         for &cmd in init_cmds.iter() {
             self.send_cmd(cmd);
         }
+        self.scan_start = Instant::now();
+    }
+
+    // Kept across initialize(), so it can be set before the display comes up
+    pub fn set_contrast(&mut self, level: u8) {
+        self.contrast = level;
+        self.send_cmd_with_arg(SET_CONTRAST, level);
+    }
+
+    pub fn get_contrast(&self) -> u8 {
+        self.contrast
+    }
+
+    // Inverse lights every pixel that's off in RAM and vice versa, RAM itself is left alone
+    pub fn set_inverted(&mut self, invert: bool) {
+        self.inverted = invert;
+        self.send_cmd(if invert { INVERSE_DISPLAY } else { NORMAL_DISPLAY });
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    // The panel does the flipping, so frames are always drawn from the top left. Segment
+    // remap only applies to data written after it, so the current frame gets resent.
+    pub fn set_orientation(&mut self, orientation: DisplayOrientation) {
+        self.orientation = orientation;
+        for cmd in orientation.commands() {
+            self.send_cmd(cmd);
+        }
+
+        self.resend_shadow();
+    }
+
+    pub fn orientation(&self) -> DisplayOrientation {
+        self.orientation
+    }
+
+    pub fn set_sync_pin(&mut self, pin: InputPin) {
+        self.sync_pin = Some(pin);
+    }
+
+    // Frame period = divide ratio * (phase 1 + phase 2 + 50) DCLKs * multiplex ratio / oscillator
+    pub fn vsync_period(&self) -> Duration {
+        let divide = (CLOCK_DIVIDE & 0x0F) as u64 + 1;
+        let dclks_per_row = (PRECHARGE_PERIOD & 0x0F) as u64 + (PRECHARGE_PERIOD >> 4) as u64 + 50;
+        let rows = MULTIPLEX_RATIO as u64 + 1;
+        Duration::from_nanos(divide * dclks_per_row * rows * 1_000_000_000 / OSC_FREQ_HZ)
+    }
+
+    // Blocks until the display starts its next scan. With a sync pin that's the next rising
+    // edge (giving up after two periods), otherwise the next estimated scan boundary.
+    pub fn await_vsync(&mut self) {
+        let period = self.vsync_period();
+
+        if let Some(pin) = self.sync_pin.as_ref() {
+            let deadline = Instant::now() + period * 2;
+            while pin.read() == Level::High && Instant::now() < deadline {}
+            while pin.read() == Level::Low && Instant::now() < deadline {}
+            return;
+        }
+
+        let elapsed = self.scan_start.elapsed().as_nanos();
+        let next_scan = (elapsed / period.as_nanos() + 1) * period.as_nanos();
+        thread::sleep(Duration::from_nanos((next_scan - elapsed) as u64));
     }
 
     pub fn send_cmd(&mut self, cmd: u8) {
         self.dc.set_low(); // Command mode
         self.spi.write(&[cmd]).unwrap();
+
+        // Keep track of the page addressing commands for the shadow buffer
+        match cmd {
+            0xB0..=0xB7 => self.cursor.0 = (cmd - PAGE_ADDRESS_START) as usize,
+            0x00..=0x0F => self.cursor.1 = (self.cursor.1 & 0xF0) | (cmd & 0x0F) as usize,
+            0x10..=0x1F => self.cursor.1 = (self.cursor.1 & 0x0F) | (((cmd & 0x0F) as usize) << 4),
+            _ => {}
+        }
     }
     
+    // Argument bytes go out in command mode too, but mustn't be mistaken for column addresses
+    fn send_cmd_with_arg(&mut self, cmd: u8, arg: u8) {
+        self.dc.set_low(); // Command mode
+        self.spi.write(&[cmd, arg]).unwrap();
+    }
+
+    // Multi-byte commands, like send_cmd_with_arg these don't move the cursor
+    fn send_cmd_sequence(&mut self, cmds: &[u8]) {
+        self.dc.set_low(); // Command mode
+        self.spi.write(cmds).unwrap();
+    }
+
     pub fn send_data(&mut self, data: &[u8]) {
         self.dc.set_high(); // Data mode
         self.spi.write(data).unwrap();
+
+        // Horizontal addressing mode, running off the end of a page wraps onto the next one
+        for &byte in data {
+            let (page, col) = self.cursor;
+            if col < SSD1309_WIDTH {
+                self.shadow[page][col] = byte;
+            }
+            self.cursor = if col + 1 >= SSD1309_WIDTH { ((page + 1) % NUM_PAGES as usize, 0) } else { (page, col + 1) };
+        }
+    }
+
+    // Last bytes written to a page, as far as the shadow buffer knows. Only updated
+    // after the SPI write went through, so a failed write leaves stale data behind.
+    // clear() zeroes it along with the display.
+    pub fn page_bytes(&self, page: u8) -> &[u8; SSD1309_WIDTH] {
+        &self.shadow[page as usize % NUM_PAGES as usize]
+    }
+
+    pub fn assert_page_equals(&self, page: u8, expected: &[u8; SSD1309_WIDTH]) -> bool {
+        self.page_bytes(page) == expected
     }
     
     pub fn clear(&mut self) {
@@ -115,12 +464,71 @@ impl DisplayInterface {
         self.send_cmd(DISPLAY_ON);
     }
 
+    #[deprecated(note = "use animate_shift_up, which lets the caller do work between steps")]
     pub fn shift_up(&mut self, shift_amount: usize, delay: u64) {
+        self.animate_shift_up(shift_amount, delay, || {});
+    }
+
+    // Moves the display start line up one row per step, calling on_frame after each step's
+    // delay so the caller can keep the CPU and keypad going during the animation
+    pub fn animate_shift_up(&mut self, shift_amount: usize, delay_ms: u64, mut on_frame: impl FnMut()) {
         for vertical_start in 0..shift_amount {
             let start_point: u8 = (vertical_start % 64) as u8;
             self.send_cmd(0x40 | (start_point & VERT_START_MASK));
-            thread::sleep(Duration::from_millis(delay));
-        }    
+            thread::sleep(Duration::from_millis(delay_ms));
+            on_frame();
+        }
+    }
+
+    // Scrolls pages start_page..=end_page across the whole width, wrapping round, until
+    // stop_scroll(). The panel does the scrolling itself, so nothing needs sending meanwhile.
+    pub fn start_horizontal_scroll(&mut self, direction: ScrollDirection, start_page: u8, end_page: u8, interval: ScrollInterval) {
+        // Changing the setup while a scroll is running corrupts RAM
+        if self.scrolling {
+            self.stop_scroll();
+        }
+
+        let cmd = match direction {
+            ScrollDirection::Left => SCROLL_LEFT,
+            ScrollDirection::Right => SCROLL_RIGHT
+        };
+        self.send_cmd_sequence(&[
+            cmd,
+            0x00,
+            start_page & 0x07,
+            interval as u8,
+            end_page & 0x07,
+            0x00,
+            0x00,
+            (SSD1309_WIDTH - 1) as u8
+        ]);
+        self.send_cmd(ACTIVATE_SCROLL);
+        self.scrolling = true;
+    }
+
+    // The datasheet wants RAM rewritten after a scroll before the next one starts, and what's
+    // in RAM has been moved by the scroll anyway, so the shadow buffer goes back out
+    pub fn stop_scroll(&mut self) {
+        self.send_cmd(DEACTIVATE_SCROLL);
+        self.scrolling = false;
+
+        self.resend_shadow();
+    }
+
+    // Startup sequence so the panel doesn't sit blank while everything else comes up: solid
+    // white, the logo if there is one, then the logo wiped upwards. About 1.2 seconds.
+    pub fn boot_animation(&mut self, logo: Option<&[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]>) {
+        self.fill();
+        thread::sleep(Duration::from_millis(200));
+        if let Some(logo) = logo {
+            self.display_2d_array(*logo);
+        }
+        thread::sleep(Duration::from_millis(500));
+        self.animate_shift_up(64, 8, || {});
+
+        // Back to the normal start line before anything else gets drawn
+        self.send_cmd(0x40);
+        self.clear();
     }
 
     pub fn fill(&mut self) {
@@ -132,11 +540,155 @@ impl DisplayInterface {
         }
     }
 
+    pub fn fill_region(&mut self, col_start: u8, page_start: u8, col_count: u8, page_count: u8, pattern: u8) -> Result<(), DisplayError> {
+        for (cmds, data) in Self::fill_region_sequence(col_start, page_start, col_count, page_count, pattern)? {
+            for cmd in cmds {
+                self.send_cmd(cmd);
+            }
+            self.send_data(&data);
+        }
+
+        Ok(())
+    }
+
+    // What fill_region sends for each page: the page and column address commands, then the
+    // pattern for just the columns inside the region
+    pub fn fill_region_sequence(col_start: u8, page_start: u8, col_count: u8, page_count: u8, pattern: u8) -> Result<Vec<PageWrite>, DisplayError> {
+        if col_start as usize + col_count as usize > SSD1309_WIDTH || page_start as usize + page_count as usize > NUM_PAGES as usize {
+            return Err(DisplayError::RegionOutOfBounds);
+        }
+
+        Ok((page_start..page_start + page_count)
+            .map(|page| {
+                let cmds = [
+                    PAGE_ADDRESS_START + page,
+                    LOWER_COLUMN_START | (col_start & 0x0F),
+                    UPPER_COLUMN_START | (col_start >> 4)
+                ];
+                (cmds, vec![pattern; col_count as usize])
+            })
+            .collect())
+    }
+
+    // Writes data into a block of pages and columns (both ends inclusive), a page at a time.
+    // data holds the region's rows of page bytes back to back, top page first.
+    pub fn display_region(&mut self, col_start: u8, col_end: u8, page_start: u8, page_end: u8, data: &[u8]) -> Result<(), DisplayError> {
+        if col_start > col_end || col_end as usize >= SSD1309_WIDTH || page_start > page_end || page_end >= NUM_PAGES {
+            return Err(DisplayError::RegionOutOfBounds);
+        }
+        let width = (col_end - col_start + 1) as usize;
+        if data.len() != width * (page_end - page_start + 1) as usize {
+            return Err(DisplayError::BufferSizeMismatch);
+        }
+
+        for (page, row) in (page_start..=page_end).zip(data.chunks(width)) {
+            self.send_cmd(PAGE_ADDRESS_START + page);
+            self.send_cmd(LOWER_COLUMN_START | (col_start & 0x0F));
+            self.send_cmd(UPPER_COLUMN_START | (col_start >> 4));
+            self.send_data(row);
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_region(&mut self, col: u8, page: u8, w: u8, h_pages: u8) -> Result<(), DisplayError> {
+        self.fill_region(col, page, w, h_pages, 0x00)
+    }
+
+    // Writes a line of text into a single page, anything past the right edge is dropped
+    pub fn draw_text(&mut self, page: u8, col_start: u8, text: &str) -> Result<(), DisplayError> {
+        if page >= NUM_PAGES || col_start as usize >= SSD1309_WIDTH {
+            return Err(DisplayError::RegionOutOfBounds);
+        }
+
+        let mut row: Vec<u8> = Vec::with_capacity(SSD1309_WIDTH);
+        for ch in text.chars() {
+            row.extend_from_slice(&text_glyph(ch));
+            row.push(0x00); // Spacing between characters
+        }
+        row.truncate(SSD1309_WIDTH - col_start as usize);
+
+        self.send_cmd(PAGE_ADDRESS_START + page);
+        self.send_cmd(LOWER_COLUMN_START | (col_start & 0x0F));
+        self.send_cmd(UPPER_COLUMN_START | (col_start >> 4));
+        self.send_data(&row);
+
+        Ok(())
+    }
+
     pub fn display_2d_array(&mut self, array: [[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) {
+        let pages = Self::scale_to_pages(&array);
+        self.send_changed_pages(&pages);
+    }
+
+    // SUPER-CHIP hi-res frames already match the panel, so they go out 1:1 without scaling
+    pub fn display_hires_array(&mut self, array: &[[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT]) {
+        let mut pages: [[u8; SSD1309_WIDTH]; NUM_PAGES as usize] = [[0; SSD1309_WIDTH]; NUM_PAGES as usize];
+        for (row, row_pixels) in array.iter().enumerate() {
+            for (col, &value) in row_pixels.iter().enumerate() {
+                if value {
+                    pages[row / 8][col] |= 1 << (row % 8);
+                }
+            }
+        }
+
+        self.send_changed_pages(&pages);
+    }
+
+    // The SSD1309 is monochrome, so XO-CHIP's extra colours are faked over time. Pixels on
+    // plane 0 are always lit and pixels only on plane 1 are lit every other frame.
+    pub fn display_xo_chip_planes(&mut self, p0: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT], p1: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT], frame: u64) {
+        let mut combined = *p0;
+        if frame % 2 == 1 {
+            for (row, plane_row) in combined.iter_mut().zip(p1.iter()) {
+                for (pixel, &plane_pixel) in row.iter_mut().zip(plane_row.iter()) {
+                    *pixel |= plane_pixel;
+                }
+            }
+        }
+        self.display_2d_array(combined);
+    }
+
+    // Only resend the pages whose source rows differ between the two frames
+    pub fn selective_update(&mut self, frame: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT], prev: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) {
+        let pages = Self::scale_to_pages(frame);
+        let rows_per_page = SOURCE_HEIGHT / NUM_PAGES as usize;
+
+        for page in 0..NUM_PAGES {
+            let rows = page as usize * rows_per_page..(page as usize + 1) * rows_per_page;
+            if frame[rows.clone()] != prev[rows] {
+                self.send_page(page, &pages[page as usize]);
+            }
+        }
+    }
+
+    // Skips pages the shadow buffer says already hold the same bytes, which is most of them
+    // on a mostly static screen
+    fn send_changed_pages(&mut self, pages: &[[u8; SSD1309_WIDTH]; NUM_PAGES as usize]) {
+        for page in 0..NUM_PAGES {
+            if pages[page as usize] != self.shadow[page as usize] {
+                self.send_page(page, &pages[page as usize]);
+            }
+        }
+    }
+
+    // Writes the whole of RAM again from the shadow buffer
+    fn resend_shadow(&mut self) {
+        let shadow = self.shadow;
+        for (page, data) in (0..NUM_PAGES).zip(shadow.iter()) {
+            self.send_page(page, data);
+        }
+    }
+
+    fn send_page(&mut self, page: u8, data: &[u8; SSD1309_WIDTH]) {
+        // A whole page always fits, so this can't fail
+        self.display_region(0, SSD1309_WIDTH as u8 - 1, page, page, data).unwrap();
+    }
+
+    fn scale_to_pages(array: &[[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) -> [[u8; SSD1309_WIDTH]; NUM_PAGES as usize] {
         let mut pages: [[u8; SSD1309_WIDTH]; NUM_PAGES as usize] = [[0; SSD1309_WIDTH]; NUM_PAGES as usize];
-        for row in 0..SOURCE_HEIGHT {
-            for col in 0..SOURCE_WIDTH {
-                let value = array[row][col];
+        for (row, row_pixels) in array.iter().enumerate() {
+            for (col, &value) in row_pixels.iter().enumerate() {
                 if value {
                     // Scale the coordinates
                     let x0 = col * 2;
@@ -155,12 +707,32 @@ impl DisplayInterface {
                 }
             }
         }
+        pages
+    }
+}
 
-        for page in 0..8 {
-            self.send_cmd(PAGE_ADDRESS_START + page);
-            self.send_cmd(0x00);
-            self.send_cmd(0x10);
-            self.send_data(&pages[page as usize]);
-        }
+impl ChipDisplay for DisplayInterface {
+    fn initialize(&mut self) {
+        DisplayInterface::initialize(self);
+    }
+
+    fn clear(&mut self) {
+        DisplayInterface::clear(self);
+    }
+
+    fn display_2d_array(&mut self, array: [[bool; SOURCE_WIDTH]; SOURCE_HEIGHT]) {
+        DisplayInterface::display_2d_array(self, array);
+    }
+
+    fn display_hires_array(&mut self, array: &[[bool; SSD1309_WIDTH]; HIRES_SOURCE_HEIGHT]) {
+        DisplayInterface::display_hires_array(self, array);
+    }
+
+    fn turn_on(&mut self) {
+        DisplayInterface::turn_on(self);
+    }
+
+    fn turn_off(&mut self) {
+        DisplayInterface::turn_off(self);
     }
 }
@@ -0,0 +1,575 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use rppal::gpio::{Gpio, InputPin, Trigger};
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use crate::audio::{AudioOutput, GpioBuzzer, SilentAudio};
+use crate::chip8::{Chip8, CycleContext, ProfilePhase, StepResult, DEFAULT_TIMER_HZ};
+use crate::display::{DisplayError, DisplayInterface, DisplayOrientation};
+use crate::error::{Chip8Error, EmulatorError};
+use crate::keypad::{KeyDebouncer, KeypadInput, MatrixKeypadInput};
+use crate::quirks::Quirks;
+
+// How long to back off for when the ROM is just spinning on a key, when running unlimited
+const SPIN_WAIT_SLEEP: Duration = Duration::from_millis(1);
+
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+
+// Holding F + 0 steps through the contrast presets, the last one picked is saved for next boot
+const CONTRAST_PRESETS: [u8; 3] = [0x1F, 0x7F, 0xCF];
+const CONTRAST_HOLD: Duration = Duration::from_secs(1);
+pub const CONTRAST_FILE: &str = "data/contrast.bin";
+
+// Time between renders in RenderMode::VBlankSync
+const VBLANK_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Two bars in the top right corner while stopped at a breakpoint, until the next render overwrites it
+const PAUSE_ICON_COL: u8 = 122;
+const PAUSE_ICON: [u8; 6] = [0x7E, 0x7E, 0x00, 0x00, 0x7E, 0x7E];
+
+// When the game loop sends the Chip8 display to the OLED
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    // After every cycle that drew
+    Immediate,
+    // Once every n draws, fewer SPI transfers for ROMs that draw a sprite at a time
+    Batched(u32),
+    // At most once per 60Hz frame, however many draws happened in it
+    VBlankSync
+}
+
+// Where the game loop gets the time from, swapped out when testing timing
+pub trait TimerSource {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemTimer;
+
+impl TimerSource for SystemTimer {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// What the game loop does once the ROM exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitBehavior {
+    // Stop and hand back V1 (for when running my menu ROM)
+    ReturnMenuCode,
+    // Keep a copy of the machine state around before returning
+    SaveStateAndReturn,
+    // Start the ROM over, only the end button or max_cycles stops the loop
+    ResetAndRestart
+}
+
+// How fast a ROM runs, the menu and the games each get their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmulatorConfig {
+    // 0 runs as fast as possible
+    pub fps: u64,
+    // Cycles run each frame, the timers run at timer_hz whatever this is
+    pub cycles_per_frame: u32,
+    pub timer_hz: u64,
+    // Turn the display off after this long without a key change or a draw, None keeps it on
+    pub idle_timeout: Option<Duration>
+}
+
+// 60 frames of 10 cycles, Octo's default of 600 instructions a second
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        EmulatorConfig {
+            fps: 60,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            timer_hz: DEFAULT_TIMER_HZ,
+            idle_timeout: None
+        }
+    }
+}
+
+// When run_game_loop stops, on top of the end button
+pub struct GameLoopOptions {
+    pub max_cycles: Option<u64>,
+    pub on_exit: ExitBehavior
+}
+
+impl Default for GameLoopOptions {
+    fn default() -> Self {
+        GameLoopOptions {
+            max_cycles: None,
+            on_exit: ExitBehavior::ReturnMenuCode
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameLoopResult {
+    pub exit_code: u8,
+    pub cycles_run: u64,
+    pub frames_rendered: u64,
+    pub runtime: Duration
+}
+
+pub struct HardwareConfig {
+    pub quirks: Quirks,
+    pub debug: bool,
+
+    // SPI display
+    pub spi_clock_hz: u32,
+    pub dc_pin: u8,
+    pub rst_pin: u8,
+    // Frame sync output of the panel, when the breakout exposes it
+    pub sync_pin: Option<u8>,
+    pub orientation: DisplayOrientation,
+
+    // Buzzer is optional, builds without one just stay quiet
+    pub buzzer_pin: Option<u8>,
+
+    // Push button that ends the current ROM
+    pub end_pin: u8,
+
+    // Keypad matrix
+    pub row_pins: [u8; 4],
+    pub col_pins: [u8; 4],
+    pub key_map: [[u8; 4]; 4],
+    // How long a key has to stay put before a press or release counts
+    pub debounce_ms: u64
+}
+
+// Wiring of my own board
+impl Default for HardwareConfig {
+    fn default() -> Self {
+        HardwareConfig {
+            quirks: Quirks::default(),
+            debug: false,
+            spi_clock_hz: 8_000_000,
+            dc_pin: 23,
+            rst_pin: 24,
+            sync_pin: None,
+            orientation: DisplayOrientation::Normal,
+            buzzer_pin: Some(25),
+            end_pin: 16,
+            row_pins: [4, 27, 0, 5],
+            col_pins: [2, 3, 6, 13],
+            key_map: [
+                [0x1, 0x2, 0x3, 0xC],
+                [0x4, 0x5, 0x6, 0xD],
+                [0x7, 0x8, 0x9, 0xE],
+                [0xA, 0x0, 0xB, 0xF],
+            ],
+            debounce_ms: 5
+        }
+    }
+}
+
+pub struct Chip8Emulator {
+    chip8: Chip8,
+    display: DisplayInterface,
+    audio: Box<dyn AudioOutput>,
+    keypad: Box<dyn KeypadInput>,
+    debouncer: KeyDebouncer,
+    timer_source: Box<dyn TimerSource>,
+    audio_cb: Box<dyn Fn(bool, u8)>,
+    saved_state: Option<Chip8>,
+    render_mode: RenderMode,
+
+    // End current ROM button, kept alive so its interrupt stays registered
+    _end_button: InputPin,
+    end_requested: Arc<AtomicBool>
+}
+
+impl Chip8Emulator {
+    pub fn new(config: HardwareConfig) -> Result<Self, EmulatorError> {
+        let mut chip8 = Chip8::new(config.quirks);
+        chip8.debug = config.debug;
+
+        // SPI setup: SPI0, CE0, Mode0
+        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, config.spi_clock_hz, Mode::Mode0)?;
+
+        // rppal GPIO setup
+        let gpio = Gpio::new()?;
+        let dc = gpio.get(config.dc_pin)?.into_output();   // Data/Command pin
+        let rst = gpio.get(config.rst_pin)?.into_output(); // Reset pin
+
+        let mut display = DisplayInterface::new(spi, dc, rst, config.orientation);
+        if let Some(pin) = config.sync_pin {
+            display.set_sync_pin(gpio.get(pin)?.into_input());
+        }
+        display.initialize();
+        display.clear();
+
+        let audio: Box<dyn AudioOutput> = match config.buzzer_pin {
+            Some(pin) => Box::new(GpioBuzzer::new(gpio.get(pin)?.into_output_low())),
+            None => Box::new(SilentAudio::default())
+        };
+
+        // Released button sets the flag from the interrupt thread
+        let end_requested = Arc::new(AtomicBool::new(false));
+        let mut end_button = gpio.get(config.end_pin)?.into_input_pullup();
+        let end_flag = Arc::clone(&end_requested);
+        end_button.set_async_interrupt(Trigger::RisingEdge, Some(Duration::from_millis(20)), move |_| {
+            end_flag.store(true, Ordering::SeqCst);
+        })?;
+
+        let keypad = MatrixKeypadInput::new(&gpio, config.row_pins, config.col_pins, config.key_map)?;
+
+        Ok(Chip8Emulator {
+            chip8,
+            display,
+            audio,
+            keypad: Box::new(keypad),
+            debouncer: KeyDebouncer::new(config.debounce_ms),
+            timer_source: Box::new(SystemTimer),
+            audio_cb: Box::new(|_, _| {}),
+            saved_state: None,
+            render_mode: RenderMode::Immediate,
+            _end_button: end_button,
+            end_requested
+        })
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    pub fn display_mut(&mut self) -> &mut DisplayInterface {
+        &mut self.display
+    }
+
+    pub fn set_timer_source(&mut self, timer_source: Box<dyn TimerSource>) {
+        self.timer_source = timer_source;
+    }
+
+    // Replaces the GPIO keypad, e.g. with a ScriptedKeypad
+    pub fn set_keypad_input(&mut self, keypad: Box<dyn KeypadInput>) {
+        self.keypad = keypad;
+    }
+
+    // Replaces the buzzer, e.g. with SilentAudio
+    pub fn set_audio_output(&mut self, audio: Box<dyn AudioOutput>) {
+        self.audio = audio;
+    }
+
+    // Called on sound on/off transitions, on top of driving the audio output
+    pub fn set_audio_callback(&mut self, audio_cb: Box<dyn Fn(bool, u8)>) {
+        self.audio_cb = audio_cb;
+    }
+
+    pub fn display_rendering_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    // Machine state saved by the last ExitBehavior::SaveStateAndReturn run
+    pub fn take_saved_state(&mut self) -> Option<Chip8> {
+        self.saved_state.take()
+    }
+
+    pub fn load_rom_file(&mut self, path: &str) -> Result<(), EmulatorError> {
+        self.chip8.load_rom(path)?;
+        self.chip8.store_checksum();
+        Ok(())
+    }
+
+    // Same as load_rom_file, for ROMs built into the binary
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        self.chip8.load_rom_bytes(data)?;
+        self.chip8.store_checksum();
+        Ok(())
+    }
+
+    fn set_sound(&mut self, active: bool) {
+        if active {
+            self.audio.play();
+        } else {
+            self.audio.stop();
+        }
+        (self.audio_cb)(active, self.chip8.audio_pitch());
+    }
+
+    fn cycle_contrast(&mut self) {
+        let current = self.display.get_contrast();
+        let next = CONTRAST_PRESETS.iter().copied().find(|&level| level > current).unwrap_or(CONTRAST_PRESETS[0]);
+        self.display.set_contrast(next);
+        if let Err(err) = std::fs::write(CONTRAST_FILE, [next]) {
+            log::warn!("Couldn't save contrast to {}: {}", CONTRAST_FILE, err);
+        }
+    }
+
+
+    fn show_pause_icon(&mut self) -> Result<(), DisplayError> {
+        let col_end = PAUSE_ICON_COL + PAUSE_ICON.len() as u8 - 1;
+        self.display.display_region(PAUSE_ICON_COL, col_end, 0, 0, &PAUSE_ICON)
+    }
+
+    // Runs the loaded ROM until it exits or the end button is pressed, returns V1
+    // (for when running my menu ROM). An fps of 0 in config runs as fast as possible.
+    pub fn run(&mut self, config: &EmulatorConfig) -> Result<u8, EmulatorError> {
+        Ok(self.run_game_loop(config, GameLoopOptions::default())?.exit_code)
+    }
+
+    pub fn run_game_loop(&mut self, config: &EmulatorConfig, options: GameLoopOptions) -> Result<GameLoopResult, Chip8Error> {
+        // Don't run a ROM that changed since it was loaded
+        if !self.chip8.verify_checksum() {
+            self.display.clear();
+            if let Err(err) = self.display.draw_text(3, 0, "ROM CHECKSUM ERROR") {
+                log::warn!("Couldn't show the checksum error: {}", err);
+            }
+            return Err(Chip8Error::ChecksumMismatch);
+        }
+
+        self.chip8.set_timer_hz(config.timer_hz);
+
+        let start = self.timer_source.now();
+        let mut last_timer_tick = start;
+        let mut sound_active = false;
+        let mut cycles_run: u64 = 0;
+        let mut frames_rendered: u64 = 0;
+
+        // Ignore presses from before the ROM started
+        self.end_requested.store(false, Ordering::SeqCst);
+
+        let limit_frames: bool = config.fps != 0;
+        let mut cycle_speed: u64 = 0;
+        if limit_frames {
+            cycle_speed = 1_000_000 / config.fps; // convert fps into how long each frame is, to reach that fps
+        }
+
+        let cycle_duration = Duration::from_micros(cycle_speed);    // Controls cycles per second
+
+        let mut ctx = CycleContext { frame_parity: 0 };
+        let mut overlay_chord_held = false;
+        let mut contrast_chord_since: Option<Instant> = None;
+        let mut last_hash = self.chip8.display_buffer_hash();
+        let mut batched_draws: u32 = 0;
+        let mut last_render = start;
+        let mut at_breakpoint = false;
+        let mut break_keys_held = false;
+        let mut last_keys = [false; 16];
+        let mut last_activity = start;
+        let mut display_sleeping = false;
+
+        'running: loop {
+            let loop_start = self.timer_source.now();
+
+            if options.max_cycles.is_some_and(|max| cycles_run >= max) {
+                break 'running;
+            }
+
+            // Handle keyboard
+            let raw_keys = self.keypad.scan();
+            let keys = self.debouncer.update(raw_keys, self.timer_source.now());
+            if keys != last_keys {
+                last_activity = self.timer_source.now();
+                if display_sleeping && keys.iter().any(|&key| key) {
+                    self.display.turn_on();
+                    self.chip8.render_to(&mut self.display);
+                    display_sleeping = false;
+                }
+                last_keys = keys;
+            }
+            if !self.chip8.has_input_script() {
+                self.chip8.set_keypad(keys);
+            }
+
+            // F + 5 chord toggles the debug overlay
+            let overlay_chord = keys[0xF] && keys[0x5];
+            if overlay_chord && !overlay_chord_held {
+                self.chip8.debug_overlay_enabled = !self.chip8.debug_overlay_enabled;
+                self.chip8.render_to(&mut self.display);
+                frames_rendered += 1;
+                if self.chip8.debug_overlay_enabled && let Err(err) = self.chip8.render_debug_overlay(&mut self.display) {
+                    log::warn!("Couldn't draw the debug overlay: {}", err);
+                }
+            }
+            overlay_chord_held = overlay_chord;
+
+            let contrast_chord = keys[0xF] && keys[0x0];
+            match contrast_chord_since {
+                Some(since) if contrast_chord && self.timer_source.now() - since >= CONTRAST_HOLD => {
+                    self.cycle_contrast();
+                    contrast_chord_since = Some(self.timer_source.now()); // Keep holding to step again
+                }
+                Some(_) if !contrast_chord => contrast_chord_since = None,
+                None if contrast_chord => contrast_chord_since = Some(self.timer_source.now()),
+                _ => {}
+            }
+
+            if self.end_requested.swap(false, Ordering::SeqCst) { // Skip to next ROM (or back to menu)
+                self.chip8.halt();
+            }
+
+            // Timers
+            let now = self.timer_source.now();
+            self.chip8.tick_timers_with_debt(now - last_timer_tick);
+            last_timer_tick = now;
+            if let Err(err) = self.chip8.render_audio_visualization(&mut self.display) {
+                log::warn!("Couldn't draw the audio waveform: {}", err);
+            }
+
+            // Only report sound on/off transitions
+            if sound_active != (self.chip8.sound_timer > 0) {
+                sound_active = !sound_active;
+                self.set_sound(sound_active);
+            }
+
+            // Stopped at a breakpoint, F carries on running and any other key runs one instruction
+            let mut cycles_this_frame = config.cycles_per_frame.max(1);
+            let mut stepped = false;
+            if at_breakpoint {
+                let pressed = keys.iter().any(|&key| key);
+                cycles_this_frame = 0;
+                if pressed && !break_keys_held {
+                    self.chip8.continue_from_breakpoint();
+                    if keys[0xF] {
+                        at_breakpoint = false;
+                        cycles_this_frame = config.cycles_per_frame.max(1);
+                    } else {
+                        cycles_this_frame = 1;
+                        stepped = true;
+                    }
+                }
+                break_keys_held = pressed;
+            }
+
+            // Run Cycles, a frame's worth before the timers and display get looked at again
+            if !self.chip8.debug || !self.chip8.paused {
+                let mut draws: u32 = 0;
+                for _ in 0..cycles_this_frame {
+                    if options.max_cycles.is_some_and(|max| cycles_run >= max) || (self.chip8.debug && self.chip8.paused) {
+                        break;
+                    }
+                    let result = self.chip8.cycle_with_context(&mut ctx)?;
+                    if matches!(result.step, StepResult::Continue | StepResult::ExitRom) {
+                        cycles_run += 1;
+                    }
+                    match result.step {
+                        StepResult::Halted => break 'running,
+                        StepResult::BreakpointHit(addr) => {
+                            println!("Breakpoint at 0x{:03X}", addr);
+                            self.chip8.debug_print_breakpoint();
+                            if let Err(err) = self.show_pause_icon() {
+                                log::warn!("Couldn't draw the pause icon: {}", err);
+                            }
+                            at_breakpoint = true;
+                            break_keys_held = true; // F still held from carrying on shouldn't step straight away
+                            break;
+                        }
+                        StepResult::WatchpointHit(watchpoint) => {
+                            println!("{}", Chip8Error::WatchpointHit { watchpoint });
+                            self.chip8.debug_print_breakpoint();
+                            if let Err(err) = self.show_pause_icon() {
+                                log::warn!("Couldn't draw the pause icon: {}", err);
+                            }
+                            at_breakpoint = true;
+                            break_keys_held = true;
+                            break;
+                        }
+                        StepResult::ExitRom => match options.on_exit {
+                            ExitBehavior::ReturnMenuCode => break 'running,
+                            ExitBehavior::SaveStateAndReturn => {
+                                self.saved_state = Some(self.chip8.detached_copy());
+                                break 'running;
+                            }
+                            ExitBehavior::ResetAndRestart => {
+                                self.chip8.reset();
+                                self.display.clear();
+                                last_hash = self.chip8.display_buffer_hash();
+                                continue 'running;
+                            }
+                        },
+                        StepResult::Continue => {}
+                    }
+                    if result.drew {
+                        draws += 1;
+                    }
+                }
+                if stepped {
+                    self.chip8.debug_print_breakpoint();
+                }
+
+                // Update Display, skipping draws that left the display the way it was
+                // draw_flag as well as draws, lagged draws get flagged during timer ticks
+                let drew = draws > 0 || self.chip8.draw_flag;
+                if drew {
+                    last_activity = self.timer_source.now();
+                }
+                let render = match self.render_mode {
+                    RenderMode::Immediate => drew,
+                    RenderMode::Batched(per_render) => {
+                        batched_draws += draws;
+                        batched_draws >= per_render.max(1)
+                    }
+                    RenderMode::VBlankSync => drew && self.timer_source.now() - last_render >= VBLANK_INTERVAL
+                };
+                if render {
+                    batched_draws = 0;
+                }
+
+                let hash = self.chip8.display_buffer_hash();
+                if render && hash != last_hash {
+                    last_hash = hash;
+                    last_render = self.timer_source.now();
+                    let display_start = Instant::now();
+                    self.chip8.render_to(&mut self.display);
+                    frames_rendered += 1;
+
+                    if self.chip8.debug_overlay_enabled && let Err(err) = self.chip8.render_debug_overlay(&mut self.display) {
+                        log::warn!("Couldn't draw the debug overlay: {}", err);
+                    }
+                    self.chip8.record_profile(ProfilePhase::Display, display_start.elapsed());
+                }
+            }
+
+            // Paused or nobody playing, save the panel until a key gets pressed
+            if let Some(timeout) = config.idle_timeout
+                && !display_sleeping
+                && self.timer_source.now() - last_activity > timeout {
+                self.display.turn_off();
+                display_sleeping = true;
+            }
+
+            // Nothing useful happens until a key changes, give the CPU back for a bit
+            if !limit_frames && self.chip8.detect_spin_wait().is_some() {
+                sleep(SPIN_WAIT_SLEEP);
+            }
+
+            // Wake up early for the next timer tick rather than sleeping through it
+            let now = self.timer_source.now();
+            let elapsed = now - loop_start;
+            if limit_frames && elapsed < cycle_duration {
+                let until_tick = self.chip8.time_until_timer_expires_at(now).unwrap_or(cycle_duration);
+                let sleep_time = (cycle_duration - elapsed).min(until_tick);
+                sleep(sleep_time);
+                self.chip8.record_profile(ProfilePhase::Sleep, sleep_time);
+            }
+        };
+
+        // Turn off sound if left on
+        if sound_active {
+            self.set_sound(false);
+        }
+        if display_sleeping {
+            self.display.turn_on();
+        }
+
+        self.chip8.resume();
+
+        self.display.clear();
+
+        if self.chip8.debug {
+            print!("{}", self.chip8.instruction_histogram_chart());
+        }
+
+        Ok(GameLoopResult {
+            exit_code: self.chip8.v[1],
+            cycles_run,
+            frames_rendered,
+            runtime: self.timer_source.now() - start
+        })
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use crate::chip8::Watchpoint;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    InvalidOpcode(u16),
+    MemoryOutOfBounds(usize),
+    PcOutOfBounds(u16),
+    StackOverflow,
+    StackUnderflow,
+    InvalidRegister { start: usize, end: usize },
+    InvalidMemorySize(usize),
+    RomTooLarge { size: usize, max: usize },
+    EmptyRom,
+    NetworkError(String),
+    InvalidRomData(String),
+    InvalidSaveState(String),
+    InvalidInputScript(String),
+    InvalidConfig(String),
+    PatchOutOfRange { addr: u16, rom_end: u16 },
+    InvalidPlaneMask(u8),
+    FontsetExecution(u16),
+    ChecksumMismatch,
+    Halted,
+    BreakpointHit(u16),
+    WatchpointHit { watchpoint: Watchpoint },
+    IoError(std::io::Error)
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::InvalidOpcode(raw) => write!(f, "Invalid opcode: {:04X}", raw),
+            Chip8Error::MemoryOutOfBounds(addr) => write!(f, "Memory access out of bounds: 0x{:X}", addr),
+            Chip8Error::PcOutOfBounds(pc) => write!(f, "Program counter out of bounds: 0x{:X}", pc),
+            Chip8Error::StackOverflow => write!(f, "Stack overflow"),
+            Chip8Error::StackUnderflow => write!(f, "Stack underflow, RET with an empty stack"),
+            Chip8Error::InvalidRegister { start, end } => write!(f, "Invalid register range V{:X}-V{:X}", start, end),
+            Chip8Error::InvalidMemorySize(size) => write!(f, "Unsupported memory size: {} bytes", size),
+            Chip8Error::RomTooLarge { size, max } => write!(f, "ROM is {} bytes, maximum is {} bytes", size, max),
+            Chip8Error::EmptyRom => write!(f, "ROM is empty"),
+            Chip8Error::NetworkError(msg) => write!(f, "Network error: {}", msg),
+            Chip8Error::InvalidRomData(msg) => write!(f, "Invalid ROM data: {}", msg),
+            Chip8Error::InvalidSaveState(msg) => write!(f, "Invalid save state: {}", msg),
+            Chip8Error::InvalidInputScript(msg) => write!(f, "Invalid input script: {}", msg),
+            Chip8Error::InvalidConfig(msg) => write!(f, "Invalid ROM list: {}", msg),
+            Chip8Error::FontsetExecution(pc) => write!(f, "Executing font data at 0x{:X}, possible fontset corruption", pc),
+            Chip8Error::InvalidPlaneMask(mask) => write!(f, "Invalid XO-CHIP plane mask: {}", mask),
+            Chip8Error::PatchOutOfRange { addr, rom_end } => write!(f, "Patch address 0x{:X} is outside the ROM, which ends at 0x{:X}", addr, rom_end),
+            Chip8Error::ChecksumMismatch => write!(f, "ROM checksum mismatch, memory has been corrupted since loading"),
+            Chip8Error::Halted => write!(f, "Emulator is halted"),
+            Chip8Error::BreakpointHit(pc) => write!(f, "Breakpoint hit at 0x{:X}", pc),
+            Chip8Error::WatchpointHit { watchpoint: Watchpoint::Register { reg, value } } => write!(f, "Watchpoint hit: V{:X} = 0x{:02X}", reg, value),
+            Chip8Error::WatchpointHit { watchpoint: Watchpoint::Memory { addr, value } } => write!(f, "Watchpoint hit: [0x{:X}] = 0x{:02X}", addr, value),
+            Chip8Error::IoError(err) => write!(f, "I/O error: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::IoError(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Chip8Error {
+    fn from(err: std::io::Error) -> Self {
+        Chip8Error::IoError(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    Chip8(Chip8Error),
+    Gpio(rppal::gpio::Error),
+    Spi(rppal::spi::Error)
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::Chip8(err) => write!(f, "{}", err),
+            EmulatorError::Gpio(err) => write!(f, "GPIO error: {}", err),
+            EmulatorError::Spi(err) => write!(f, "SPI error: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulatorError::Chip8(err) => Some(err),
+            EmulatorError::Gpio(err) => Some(err),
+            EmulatorError::Spi(err) => Some(err)
+        }
+    }
+}
+
+impl From<Chip8Error> for EmulatorError {
+    fn from(err: Chip8Error) -> Self {
+        EmulatorError::Chip8(err)
+    }
+}
+
+impl From<rppal::gpio::Error> for EmulatorError {
+    fn from(err: rppal::gpio::Error) -> Self {
+        EmulatorError::Gpio(err)
+    }
+}
+
+impl From<rppal::spi::Error> for EmulatorError {
+    fn from(err: rppal::spi::Error) -> Self {
+        EmulatorError::Spi(err)
+    }
+}
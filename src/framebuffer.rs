@@ -0,0 +1,56 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use crate::chip8::Chip8;
+
+const FRAMEBUFFER_DEVICE: &str = "/dev/fb0";
+const FRAMEBUFFER_SYSFS: &str = "/sys/class/graphics/fb0";
+
+// Draws the Chip8 display to the HDMI output through /dev/fb0. Frames are built in memory
+// and written to the device in one go rather than memory mapping it.
+pub struct FramebufferRenderer {
+    device: File,
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+    scale: usize
+}
+
+impl FramebufferRenderer {
+    pub fn open(scale: usize) -> io::Result<Self> {
+        // Resolution comes through as "width,height"
+        let size = fs::read_to_string(format!("{}/virtual_size", FRAMEBUFFER_SYSFS))?;
+        let (width, height) = size.trim().split_once(',')
+            .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unreadable framebuffer size"))?;
+
+        let bits_per_pixel = fs::read_to_string(format!("{}/bits_per_pixel", FRAMEBUFFER_SYSFS))?;
+        if bits_per_pixel.trim() != "32" {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Only 32 bit framebuffers are supported"));
+        }
+
+        let device = OpenOptions::new().write(true).open(FRAMEBUFFER_DEVICE)?;
+
+        Ok(FramebufferRenderer {
+            device,
+            buffer: vec![0; width * height * 4],
+            width,
+            height,
+            scale
+        })
+    }
+
+    pub fn render(&mut self, chip8: &Chip8) -> io::Result<()> {
+        chip8.render_to_framebuffer(&mut self.buffer, self.width, self.height, self.scale);
+        self.device.write_all_at(&self.buffer, 0)
+    }
+
+    // Only redraws when the last cycles drew something
+    pub fn update(&mut self, chip8: &Chip8) -> io::Result<()> {
+        if chip8.draw_flag {
+            self.render(chip8)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+use crate::error::Chip8Error;
+use crate::quirks::Platform;
+
 pub struct Instruction {
     pub instruction: u16,
     pub nibble: u8,
@@ -5,11 +9,13 @@ pub struct Instruction {
     pub y: usize,
     pub n: u8,
     pub nn: u8,
-    pub nnn: u16
+    pub nnn: u16,
+    // Second word of 4 byte instructions, filled in by fetch
+    pub extra: Option<u16>
 }
 
 impl Instruction {
-    pub fn new(inst: u16) -> Self {
+    pub const fn new(inst: u16) -> Self {
         Instruction {
             instruction: inst,
             nibble: ((inst & 0xF000) >> 12) as u8,
@@ -23,6 +29,251 @@ impl Instruction {
             nn: (inst & 0x00FF) as u8,
             // NNN: The second, third and fourth nibbles. A 12-bit immediate memory address.
             nnn: inst & 0x0FFF,
+            extra: None,
+        }
+    }
+
+    // Bytes the instruction takes up in memory, only the XO-CHIP long load (F000 NNNN) is 4.
+    // Other platforms don't have it, there F000 is a 2 byte unknown opcode.
+    pub const fn encoding_size(&self, platform: Platform) -> u8 {
+        if self.instruction == 0xF000 && matches!(platform, Platform::XoChip) { 4 } else { 2 }
+    }
+
+    // Whether this is an opcode the disassembler has a mnemonic for, CHIP-8, SUPER-CHIP and
+    // the XO-CHIP ones the interpreter runs
+    pub const fn is_known(&self) -> bool {
+        match self.nibble {
+            0x0 => matches!(self.instruction, 0x00E0 | 0x00EE | 0x00C0..=0x00CF | 0x00FB..=0x00FC | 0x00FE..=0x00FF),
+            0x5 => matches!(self.n, 0x0 | 0x2 | 0x3),
+            0x8 => matches!(self.n, 0x0..=0x7 | 0xE),
+            0x9 => self.n == 0x0,
+            0xE => matches!(self.nn, 0x9E | 0xA1),
+            0xF => self.instruction == 0xF000 || matches!(self.nn, 0x01 | 0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65 | 0x75 | 0x85),
+            _ => true
+        }
+    }
+
+    // Cowgod style assembly, e.g. "LD V3, 0x42". Unknown opcodes come out as "DB 0x....".
+    pub fn disassemble(&self) -> String {
+        self.to_string()
+    }
+
+    // Shared with the Chip8 disassembler, which swaps in register aliases and labels
+    pub(crate) fn disassemble_with(&self, register_name: impl Fn(usize) -> String, label: impl Fn(u16) -> Option<&'static str>) -> String {
+        let address_name = |addr: u16| match label(addr) {
+            Some(name) => name.to_string(),
+            None => format!("0x{:03X}", addr)
+        };
+        if !self.is_known() {
+            return format!("DB 0x{:04X}", self.instruction);
+        }
+
+        let (vx, vy) = (register_name(self.x), register_name(self.y));
+        match (self.nibble, self.n) {
+            _ if self.instruction == 0x00E0 => "CLS".to_string(),
+            _ if self.instruction == 0x00EE => "RET".to_string(),
+            _ if self.instruction & 0xFFF0 == 0x00C0 => format!("SCD {}", self.n),
+            _ if self.instruction == 0x00FB => "SCR".to_string(),
+            _ if self.instruction == 0x00FC => "SCL".to_string(),
+            _ if self.instruction == 0x00FE => "LOW".to_string(),
+            _ if self.instruction == 0x00FF => "HIGH".to_string(),
+            _ if self.instruction == 0xF000 => match self.extra {
+                Some(addr) => match label(addr) {
+                    Some(name) => format!("LD I, {}", name),
+                    None => format!("LD I, 0x{:04X}", addr)
+                },
+                None => "LD I, ????".to_string()
+            },
+            (0x1, _) => format!("JP {}", address_name(self.nnn)),
+            (0x2, _) => format!("CALL {}", address_name(self.nnn)),
+            (0x3, _) => format!("SE {}, 0x{:02X}", vx, self.nn),
+            (0x4, _) => format!("SNE {}, 0x{:02X}", vx, self.nn),
+            (0x5, 0x0) => format!("SE {}, {}", vx, vy),
+            (0x5, 0x2) => format!("LD [I], {}-{}", vx, vy),
+            (0x5, 0x3) => format!("LD {}-{}, [I]", vx, vy),
+            (0x6, _) => format!("LD {}, 0x{:02X}", vx, self.nn),
+            (0x7, _) => format!("ADD {}, 0x{:02X}", vx, self.nn),
+            (0x8, 0x0) => format!("LD {}, {}", vx, vy),
+            (0x8, 0x1) => format!("OR {}, {}", vx, vy),
+            (0x8, 0x2) => format!("AND {}, {}", vx, vy),
+            (0x8, 0x3) => format!("XOR {}, {}", vx, vy),
+            (0x8, 0x4) => format!("ADD {}, {}", vx, vy),
+            (0x8, 0x5) => format!("SUB {}, {}", vx, vy),
+            (0x8, 0x6) => format!("SHR {}, {}", vx, vy),
+            (0x8, 0x7) => format!("SUBN {}, {}", vx, vy),
+            (0x8, 0xE) => format!("SHL {}, {}", vx, vy),
+            (0x9, 0x0) => format!("SNE {}, {}", vx, vy),
+            (0xA, _) => format!("LD I, {}", address_name(self.nnn)),
+            (0xB, _) => format!("JP V0, {}", address_name(self.nnn)),
+            (0xC, _) => format!("RND {}, 0x{:02X}", vx, self.nn),
+            (0xD, _) => format!("DRW {}, {}, {}", vx, vy, self.n),
+            (0xE, _) if self.nn == 0x9E => format!("SKP {}", vx),
+            (0xE, _) if self.nn == 0xA1 => format!("SKNP {}", vx),
+            (0xF, _) => match self.nn {
+                0x01 => format!("PLANE {}", self.x),
+                0x07 => format!("LD {}, DT", vx),
+                0x0A => format!("LD {}, K", vx),
+                0x15 => format!("LD DT, {}", vx),
+                0x18 => format!("LD ST, {}", vx),
+                0x1E => format!("ADD I, {}", vx),
+                0x29 => format!("LD F, {}", vx),
+                0x30 => format!("LD HF, {}", vx),
+                0x33 => format!("LD B, {}", vx),
+                0x55 => format!("LD [I], {}", vx),
+                0x65 => format!("LD {}, [I]", vx),
+                0x75 => format!("LD R, {}", vx),
+                0x85 => format!("LD {}, R", vx),
+                _ => format!("DB 0x{:04X}", self.instruction)
+            },
+            _ => format!("DB 0x{:04X}", self.instruction)
+        }
+    }
+}
+
+// For tools that want to reject unknown opcodes up front. fetch() still uses new(), since
+// the PC has to move past an unknown opcode like any other.
+impl TryFrom<u16> for Instruction {
+    type Error = Chip8Error;
+
+    fn try_from(raw: u16) -> Result<Self, Self::Error> {
+        let inst = Instruction::new(raw);
+        if inst.is_known() {
+            Ok(inst)
+        } else {
+            Err(Chip8Error::InvalidOpcode(raw))
+        }
+    }
+}
+
+// Same text as disassemble(), so instructions can go straight into format strings
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.disassemble_with(|reg| format!("V{:X}", reg), |_| None))
+    }
+}
+
+// Decoded form of every opcode the interpreter understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Cls,                                // 00E0
+    Ret,                                // 00EE
+    ScrollDown { n: u8 },               // 00CN (SCHIP)
+    ScrollRight,                        // 00FB (SCHIP)
+    ScrollLeft,                         // 00FC (SCHIP)
+    Lores,                              // 00FE (SCHIP)
+    Hires,                              // 00FF (SCHIP)
+    Jp(u16),                            // 1NNN
+    Call(u16),                          // 2NNN
+    SeByte { x: usize, nn: u8 },        // 3XNN
+    SneByte { x: usize, nn: u8 },       // 4XNN
+    SeReg { x: usize, y: usize },       // 5XY0
+    StoreRange { x: usize, y: usize },  // 5XY2 (XO-CHIP)
+    LoadRange { x: usize, y: usize },   // 5XY3 (XO-CHIP)
+    LdByte { x: usize, nn: u8 },        // 6XNN
+    AddByte { x: usize, nn: u8 },       // 7XNN
+    LdReg { x: usize, y: usize },       // 8XY0
+    Or { x: usize, y: usize },          // 8XY1
+    And { x: usize, y: usize },         // 8XY2
+    Xor { x: usize, y: usize },         // 8XY3
+    AddReg { x: usize, y: usize },      // 8XY4
+    Sub { x: usize, y: usize },         // 8XY5
+    Shr { x: usize, y: usize },         // 8XY6
+    Subn { x: usize, y: usize },        // 8XY7
+    Shl { x: usize, y: usize },         // 8XYE
+    SneReg { x: usize, y: usize },      // 9XY0
+    LdI(u16),                           // ANNN
+    JpOffset { x: usize, nnn: u16 },    // BNNN
+    Rnd { x: usize, nn: u8 },           // CXNN
+    Drw { x: usize, y: usize, n: u8 },  // DXYN
+    Skp { x: usize },                   // EX9E
+    Sknp { x: usize },                  // EXA1
+    LdILong,                            // F000 NNNN (XO-CHIP)
+    Plane { mask: u8 },                 // FN01 (XO-CHIP)
+    LdVxDt { x: usize },                // FX07
+    LdKey { x: usize },                 // FX0A
+    LdDtVx { x: usize },                // FX15
+    LdStVx { x: usize },                // FX18
+    AddI { x: usize },                  // FX1E
+    LdFont { x: usize },                // FX29
+    LdBigFont { x: usize },             // FX30 (SCHIP)
+    Bcd { x: usize },                   // FX33
+    Store { x: usize },                 // FX55
+    Load { x: usize },                  // FX65
+    StoreFlags { x: usize },            // FX75 (SCHIP)
+    LoadFlags { x: usize }              // FX85 (SCHIP)
+}
+
+// Opcodes without their operands, for tracking which kinds of instruction have run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpcodeClass {
+    Cls, Ret, ScrollDown, ScrollRight, ScrollLeft, Lores, Hires, Jp, Call, SeByte, SneByte, SeReg, StoreRange, LoadRange, LdByte, AddByte, LdReg, Or, And, Xor,
+    AddReg, Sub, Shr, Subn, Shl, SneReg, LdI, JpOffset, Rnd, Drw, Skp, Sknp, LdILong, Plane, LdVxDt,
+    LdKey, LdDtVx, LdStVx, AddI, LdFont, LdBigFont, Bcd, Store, Load, StoreFlags, LoadFlags
+}
+
+impl OpcodeClass {
+    pub const ALL: [OpcodeClass; 46] = [
+        OpcodeClass::Cls, OpcodeClass::Ret, OpcodeClass::ScrollDown, OpcodeClass::ScrollRight,
+        OpcodeClass::ScrollLeft, OpcodeClass::Lores, OpcodeClass::Hires, OpcodeClass::Jp, OpcodeClass::Call, OpcodeClass::SeByte,
+        OpcodeClass::SneByte, OpcodeClass::SeReg, OpcodeClass::StoreRange, OpcodeClass::LoadRange, OpcodeClass::LdByte, OpcodeClass::AddByte,
+        OpcodeClass::LdReg, OpcodeClass::Or, OpcodeClass::And, OpcodeClass::Xor, OpcodeClass::AddReg,
+        OpcodeClass::Sub, OpcodeClass::Shr, OpcodeClass::Subn, OpcodeClass::Shl, OpcodeClass::SneReg,
+        OpcodeClass::LdI, OpcodeClass::JpOffset, OpcodeClass::Rnd, OpcodeClass::Drw, OpcodeClass::Skp,
+        OpcodeClass::Sknp, OpcodeClass::LdILong, OpcodeClass::Plane, OpcodeClass::LdVxDt, OpcodeClass::LdKey, OpcodeClass::LdDtVx,
+        OpcodeClass::LdStVx, OpcodeClass::AddI, OpcodeClass::LdFont, OpcodeClass::LdBigFont,
+        OpcodeClass::Bcd, OpcodeClass::Store, OpcodeClass::Load, OpcodeClass::StoreFlags, OpcodeClass::LoadFlags
+    ];
+}
+
+impl Opcode {
+    pub const fn class(&self) -> OpcodeClass {
+        match self {
+            Opcode::Cls => OpcodeClass::Cls,
+            Opcode::Ret => OpcodeClass::Ret,
+            Opcode::ScrollDown { .. } => OpcodeClass::ScrollDown,
+            Opcode::ScrollRight => OpcodeClass::ScrollRight,
+            Opcode::ScrollLeft => OpcodeClass::ScrollLeft,
+            Opcode::Lores => OpcodeClass::Lores,
+            Opcode::Hires => OpcodeClass::Hires,
+            Opcode::Jp(_) => OpcodeClass::Jp,
+            Opcode::Call(_) => OpcodeClass::Call,
+            Opcode::SeByte { .. } => OpcodeClass::SeByte,
+            Opcode::SneByte { .. } => OpcodeClass::SneByte,
+            Opcode::SeReg { .. } => OpcodeClass::SeReg,
+            Opcode::StoreRange { .. } => OpcodeClass::StoreRange,
+            Opcode::LoadRange { .. } => OpcodeClass::LoadRange,
+            Opcode::LdByte { .. } => OpcodeClass::LdByte,
+            Opcode::AddByte { .. } => OpcodeClass::AddByte,
+            Opcode::LdReg { .. } => OpcodeClass::LdReg,
+            Opcode::Or { .. } => OpcodeClass::Or,
+            Opcode::And { .. } => OpcodeClass::And,
+            Opcode::Xor { .. } => OpcodeClass::Xor,
+            Opcode::AddReg { .. } => OpcodeClass::AddReg,
+            Opcode::Sub { .. } => OpcodeClass::Sub,
+            Opcode::Shr { .. } => OpcodeClass::Shr,
+            Opcode::Subn { .. } => OpcodeClass::Subn,
+            Opcode::Shl { .. } => OpcodeClass::Shl,
+            Opcode::SneReg { .. } => OpcodeClass::SneReg,
+            Opcode::LdI(_) => OpcodeClass::LdI,
+            Opcode::JpOffset { .. } => OpcodeClass::JpOffset,
+            Opcode::Rnd { .. } => OpcodeClass::Rnd,
+            Opcode::Drw { .. } => OpcodeClass::Drw,
+            Opcode::Skp { .. } => OpcodeClass::Skp,
+            Opcode::Sknp { .. } => OpcodeClass::Sknp,
+            Opcode::LdILong => OpcodeClass::LdILong,
+            Opcode::Plane { .. } => OpcodeClass::Plane,
+            Opcode::LdVxDt { .. } => OpcodeClass::LdVxDt,
+            Opcode::LdKey { .. } => OpcodeClass::LdKey,
+            Opcode::LdDtVx { .. } => OpcodeClass::LdDtVx,
+            Opcode::LdStVx { .. } => OpcodeClass::LdStVx,
+            Opcode::AddI { .. } => OpcodeClass::AddI,
+            Opcode::LdFont { .. } => OpcodeClass::LdFont,
+            Opcode::LdBigFont { .. } => OpcodeClass::LdBigFont,
+            Opcode::Bcd { .. } => OpcodeClass::Bcd,
+            Opcode::Store { .. } => OpcodeClass::Store,
+            Opcode::Load { .. } => OpcodeClass::Load,
+            Opcode::StoreFlags { .. } => OpcodeClass::StoreFlags,
+            Opcode::LoadFlags { .. } => OpcodeClass::LoadFlags
         }
     }
 }
@@ -0,0 +1,192 @@
+use rppal::gpio::{Gpio, InputPin, Level, OutputPin};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::error::Chip8Error;
+
+// Input script header: magic, then the number of records as a u32 (LE)
+const INPUT_SCRIPT_MAGIC: &[u8; 4] = b"CH8I";
+const INPUT_SCRIPT_HEADER_LEN: usize = 8;
+// Cycle number as a u64 and the keypad as a u16 bitmask, both LE
+const INPUT_RECORD_LEN: usize = 10;
+
+// Where the emulator reads the 16 keys from, swapped out for scripted input when testing
+pub trait KeypadInput {
+    fn scan(&mut self) -> [bool; 16];
+}
+
+// 4x4 matrix keypad, rows are driven low one at a time and the columns read back
+pub struct MatrixKeypadInput {
+    rows: Vec<OutputPin>,
+    cols: Vec<InputPin>,
+    key_map: [[u8; 4]; 4]
+}
+
+impl MatrixKeypadInput {
+    pub fn new(gpio: &Gpio, row_pins: [u8; 4], col_pins: [u8; 4], key_map: [[u8; 4]; 4]) -> Result<Self, rppal::gpio::Error> {
+        let mut rows = Vec::with_capacity(row_pins.len());
+        for pin in row_pins {
+            rows.push(gpio.get(pin)?.into_output_high());
+        }
+
+        let mut cols = Vec::with_capacity(col_pins.len());
+        for pin in col_pins {
+            cols.push(gpio.get(pin)?.into_input_pullup());
+        }
+
+        Ok(MatrixKeypadInput { rows, cols, key_map })
+    }
+}
+
+impl KeypadInput for MatrixKeypadInput {
+    fn scan(&mut self) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for (i, row) in self.rows.iter_mut().enumerate() {
+            row.set_low(); // pull current row low
+
+            for (j, col) in self.cols.iter().enumerate() {
+                let key = self.key_map[i][j];
+                keypad[key as usize] = col.read() == Level::Low;
+            }
+
+            row.set_high(); // reset row to high
+        }
+        keypad
+    }
+}
+
+// Replays key states, each one taking effect on the scan with that number (counting from 0)
+pub struct ScriptedKeypad {
+    pub events: VecDeque<(u64, [bool; 16])>,
+    pub cycle: u64,
+    keys: [bool; 16]
+}
+
+impl ScriptedKeypad {
+    pub fn new(events: impl IntoIterator<Item = (u64, [bool; 16])>) -> Self {
+        ScriptedKeypad { events: events.into_iter().collect(), cycle: 0, keys: [false; 16] }
+    }
+}
+
+impl KeypadInput for ScriptedKeypad {
+    fn scan(&mut self) -> [bool; 16] {
+        while let Some(&(at, keys)) = self.events.front() {
+            if at > self.cycle {
+                break;
+            }
+            self.keys = keys;
+            self.events.pop_front();
+        }
+        self.cycle += 1;
+        self.keys
+    }
+}
+
+// Bit N set when key N is down
+pub(crate) fn keys_to_mask(keys: &[bool; 16]) -> u16 {
+    keys.iter().enumerate().fold(0u16, |mask, (key, &down)| mask | (down as u16) << key)
+}
+
+pub(crate) fn mask_to_keys(mask: u16) -> [bool; 16] {
+    std::array::from_fn(|key| mask & (1 << key) != 0)
+}
+
+// Keypad state taking effect once the cycle counter (since reset) reaches at_cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub at_cycle: u64,
+    pub state: [bool; 16]
+}
+
+// Keypad changes tied to cycle numbers rather than scans or wall time, so replaying one
+// through Chip8::set_input_script gives exactly the same run every time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputScript {
+    events: Vec<InputEvent>
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        InputScript { events: Vec::new() }
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    // Adds a change, dropping it when the keys are the same as the last event
+    pub fn push(&mut self, at_cycle: u64, state: [bool; 16]) {
+        if self.events.last().is_some_and(|last| last.state == state) {
+            return;
+        }
+        self.events.push(InputEvent { at_cycle, state });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(INPUT_SCRIPT_HEADER_LEN + self.events.len() * INPUT_RECORD_LEN);
+        out.extend_from_slice(INPUT_SCRIPT_MAGIC);
+        out.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+        for event in self.events.iter() {
+            out.extend_from_slice(&event.at_cycle.to_le_bytes());
+            out.extend_from_slice(&keys_to_mask(&event.state).to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Chip8Error> {
+        let invalid = |msg: String| Chip8Error::InvalidInputScript(msg);
+        if data.len() < INPUT_SCRIPT_HEADER_LEN || &data[..4] != INPUT_SCRIPT_MAGIC {
+            return Err(invalid("missing CH8I header".to_string()));
+        }
+        let count = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let records = &data[INPUT_SCRIPT_HEADER_LEN..];
+        if records.len() != count * INPUT_RECORD_LEN {
+            return Err(invalid(format!("header says {} records, found {} bytes of them", count, records.len())));
+        }
+
+        let mut events = Vec::with_capacity(count);
+        for record in records.chunks_exact(INPUT_RECORD_LEN) {
+            let at_cycle = u64::from_le_bytes(record[..8].try_into().unwrap());
+            if events.last().is_some_and(|last: &InputEvent| last.at_cycle > at_cycle) {
+                return Err(invalid(format!("cycle {} comes after a later one", at_cycle)));
+            }
+            let state = mask_to_keys(u16::from_le_bytes([record[8], record[9]]));
+            events.push(InputEvent { at_cycle, state });
+        }
+        Ok(InputScript { events })
+    }
+}
+
+// Switches bounce for a few ms, so a key only changes once its raw reading has held for threshold
+pub struct KeyDebouncer {
+    threshold: Duration,
+    stable: [bool; 16],
+    last_raw: [bool; 16],
+    last_change: [Option<Instant>; 16]
+}
+
+impl KeyDebouncer {
+    pub fn new(threshold_ms: u64) -> Self {
+        KeyDebouncer {
+            threshold: Duration::from_millis(threshold_ms),
+            stable: [false; 16],
+            last_raw: [false; 16],
+            last_change: [None; 16]
+        }
+    }
+
+    pub fn update(&mut self, raw: [bool; 16], now: Instant) -> [bool; 16] {
+        for (key, &pressed) in raw.iter().enumerate() {
+            if pressed != self.last_raw[key] {
+                self.last_raw[key] = pressed;
+                self.last_change[key] = Some(now);
+            }
+
+            let settled = self.last_change[key].is_none_or(|changed| now.saturating_duration_since(changed) >= self.threshold);
+            if pressed != self.stable[key] && settled {
+                self.stable[key] = pressed;
+            }
+        }
+        self.stable
+    }
+}
@@ -0,0 +1,13 @@
+pub mod audio;
+pub mod chip8;
+pub mod config;
+pub mod display;
+pub mod emulator;
+pub mod error;
+pub mod framebuffer;
+pub mod instruction;
+pub mod keypad;
+pub mod opcode_handler;
+pub mod quirks;
+pub mod report;
+pub mod rom_analyzer;
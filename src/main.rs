@@ -1,178 +1,241 @@
-use std::time::{Duration, Instant};
-use rppal::{spi::{Spi, Mode, SlaveSelect, Bus}, gpio::{Gpio, Level}};
-use std::thread::sleep;
-
-mod display;
-mod chip8;
-mod quirks;
-mod instruction;
-use display::DisplayInterface;
-use chip8::Chip8;
-use quirks::Quirks;
-
-// Emulator Cycle Return Value
-const EXIT_ROM: u8 = 1;
-
-// Load point for my custom game-choosing ROM
+use std::time::Duration;
+
+use chip8_embedded_emulator::chip8::Chip8;
+use chip8_embedded_emulator::config::{load_rom_list, RomConfig};
+use chip8_embedded_emulator::display::{ScrollDirection, ScrollInterval};
+use chip8_embedded_emulator::emulator::{Chip8Emulator, EmulatorConfig, GameLoopOptions, HardwareConfig, CONTRAST_FILE};
+use chip8_embedded_emulator::quirks::Quirks;
+
+// My custom game-choosing ROM, built in since it gets loaded again after every game.
+// build.rs checks it's there and fits in memory.
+const MENU_ROM: &[u8] = include_bytes!("../roms/menu-new.ch8");
+// Load point for the game names the menu shows
 const MENU_LOAD_LOC: usize = 0x500;
-
-// Display Pin constants
-const DC_PIN: u8 = 23;
-const RST_PIN: u8 = 24;
-
-// Buzzer Pin constant
-const BUZZER_PIN: u8 = 25;
-
-// Pin for push button that ends current ROM
-const END_PIN: u8 = 16;
-
-// Keypad Pin constants
-const ROW_PINS: [u8; 4] = [4, 27, 0, 5];
-const COL_PINS: [u8; 4] = [2, 3, 6, 13];
-
-const KEY_MAP: [[u8; 4]; 4] = [
-    [0x1, 0x2, 0x3, 0xC],
-    [0x4, 0x5, 0x6, 0xD],
-    [0x7, 0x8, 0x9, 0xE],
-    [0xA, 0x0, 0xB, 0xF],
+const ROM_LIST: &str = "data/roms.toml";
+// SCHIP games' FX75 flags (high scores etc.), saved after each game
+const RPL_FILE: &str = "data/rpl.bin";
+
+// How long --report runs each ROM for
+const REPORT_CYCLES: u64 = 10_000;
+
+// 10 seconds at 60fps
+const HEADLESS_FRAMES: u32 = 600;
+
+// The picked game's name scrolls past for this long before it starts
+const TITLE_MARQUEE: Duration = Duration::from_millis(1500);
+const TITLE_PAGE: u8 = 3;
+
+// The display goes to sleep after this long without a key change or anything being drawn
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Shown by the boot animation, # is a lit pixel
+const BOOT_LOGO_ART: [&str; 32] = [
+    "################################################################",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#........######..##..##..######..######..........######........#",
+    "#........######..##..##..######..######..........######........#",
+    "#........##......##..##....##....##..##..........##..##........#",
+    "#........##......##..##....##....##..##..........##..##........#",
+    "#........##......######....##....######..######..######........#",
+    "#........##......######....##....######..######..######........#",
+    "#........##......##..##....##....##..............##..##........#",
+    "#........##......##..##....##....##..............##..##........#",
+    "#........######..##..##..######..##..............######........#",
+    "#........######..##..##..######..##..............######........#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "#..............................................................#",
+    "################################################################"
 ];
-
-fn run_game(chip8: &mut Chip8, fps: u64) -> Result<u8, Box<dyn std::error::Error>> {
-    let timer_interval = Duration::from_millis(16);
-    let mut last_timer_tick = Instant::now();
-
-    // SPI setup: SPI0, CE0, 8 MHz, Mode0
-    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 8_000_000, Mode::Mode0)?;
-    
-    // rppal GPIO setup
-    let gpio = Gpio::new()?;
-    let dc = gpio.get(DC_PIN)?.into_output();   // Data/Command pin
-    let rst = gpio.get(RST_PIN)?.into_output(); // Reset pin
-
-    let mut buzzer = gpio.get(BUZZER_PIN)?.into_output();
-    buzzer.set_low();
-
-    let rom_button = gpio.get(END_PIN)?.into_input_pullup(); // End current ROM pin
-
-    // Create SPI interface
-    let mut screen = DisplayInterface::new(spi, dc, rst);
-
-    // Initialize the display
-    screen.initialize();
-
-    screen.clear();
-
-    // Get all keypad row pins
-    let mut rows: Vec<_> = ROW_PINS.iter()
-        .map(|&pin| gpio.get(pin).unwrap().into_output_high())
-        .collect();
-
-    // Get all keypad col pins
-    let cols: Vec<_> = COL_PINS.iter()
-        .map(|&pin| gpio.get(pin).unwrap().into_input_pullup())
-        .collect();
-
-    let limit_frames: bool = fps != 0;
-    let mut cycle_speed: u64 = 0; 
-    if limit_frames {
-        cycle_speed = 1_000_000 / fps; // convert fps into how long each frame is, to reach that fps
+const BOOT_LOGO: [[bool; 64]; 32] = logo_from_art(&BOOT_LOGO_ART);
+
+const fn logo_from_art(art: &[&str; 32]) -> [[bool; 64]; 32] {
+    let mut logo = [[false; 64]; 32];
+    let mut y = 0;
+    while y < 32 {
+        let row = art[y].as_bytes();
+        let mut x = 0;
+        while x < 64 {
+            logo[y][x] = row[x] == b'#';
+            x += 1;
+        }
+        y += 1;
     }
+    logo
+}
 
-    let cycle_duration = Duration::from_micros(cycle_speed);    // Controls cycles per second
-
-    'running: loop {
-        let loop_start = Instant::now();
-
-        // Handle keyboard
-        for (i, row) in rows.iter_mut().enumerate() {
-            row.set_low(); // pull current row low
-
-            for (j, col) in cols.iter().enumerate() {
-                let key = KEY_MAP[i][j];
-                if col.read() == Level::Low {
-                    chip8.keypad[key as usize] = true;
-                } else {
-                    chip8.keypad[key as usize] = false;
-                }
-            }
-
-            row.set_high(); // reset row to high
-        }
+// Cycles kept while a game runs, printed if it stops with an error
+const TRACE_LEN: usize = 32;
 
-        if rom_button.is_low() { // Skip to next ROM (or back to menu)
-            while rom_button.is_low() {} // Wait for release to avoid skipping next ROM instantly
-            break 'running;
-        }
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let diagnose = std::env::args().any(|arg| arg == "--diagnose");
+    let benchmark = std::env::args().any(|arg| arg == "--benchmark");
+
+    // --report <rom> doesn't touch the hardware, so it runs before the emulator is set up
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(rom_path) = args.iter().position(|arg| arg == "--report").and_then(|index| args.get(index + 1)) {
+        let rom = std::fs::read(rom_path)?;
+        let report = Chip8::generate_test_report(&rom, REPORT_CYCLES)?;
+        std::fs::create_dir_all("reports")?;
+        std::fs::write(format!("reports/{}.json", report.rom_hash_hex()), report.to_json())?;
+        println!("{}", report.summary());
+        return Ok(());
+    }
 
-        // Timers
-        if last_timer_tick.elapsed() >= timer_interval {
-            if chip8.delay_timer > 0 {
-                chip8.delay_timer -= 1;
+    // --headless <rom> runs a ROM for up to 10 seconds without any hardware, then prints the display
+    if let Some(rom_path) = args.iter().position(|arg| arg == "--headless").and_then(|index| args.get(index + 1)) {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.load_rom_bytes(&std::fs::read(rom_path)?)?;
+        for _ in 0..HEADLESS_FRAMES {
+            let frame = chip8.step_one_frame(60);
+            if let Some(err) = frame.error {
+                return Err(err.into());
             }
-            if chip8.sound_timer > 0 {
-                buzzer.set_high();
-                chip8.sound_timer -= 1;
-            } else {
-                buzzer.set_low();
-            }
-            last_timer_tick = Instant::now();
-        }
-
-        // Run Cycle 
-        if !chip8.debug || (chip8.debug &&!chip8.paused) {
-            let result = chip8.cycle().unwrap();
-            
-            if result == EXIT_ROM {
-                break 'running;
+            if frame.exit_code.is_some() {
+                break;
             }
+            chip8.mark_rendered();
         }
+        #[cfg(feature = "std")]
+        print!("{}", chip8.display_as_ascii());
+        return Ok(());
+    }
 
-        // Update Display
-        if chip8.draw_flag {
-            chip8.draw_flag = false;
-            screen.display_2d_array(chip8.display);
+    let roms = match load_rom_list(ROM_LIST) {
+        Ok(roms) => roms,
+        Err(err) => {
+            eprintln!("Couldn't load the ROM list: {}", err);
+            return Err(err.into());
         }
-
-        let elapsed = loop_start.elapsed();
-        if limit_frames && elapsed < cycle_duration {
-            sleep(cycle_duration - elapsed);
-        }    
     };
+    let Some(menu) = roms.iter().find(|rom| rom.id == "menu") else {
+        return Err(format!("{} has no [menu] entry", ROM_LIST).into());
+    };
+    let games: Vec<&RomConfig> = roms.iter().filter(|rom| rom.id != "menu").collect();
+    let game_names: Vec<&str> = games.iter().map(|game| game.name.as_str()).collect();
 
-    // Turn off buzzer if left on
-    buzzer.set_low();
-
-    screen.clear();
+    let hardware = HardwareConfig {
+        quirks: Quirks::chip8(),
+        debug: false,
+        ..HardwareConfig::default()
+    };
+    let mut emulator = Chip8Emulator::new(hardware)?;
+
+    // The menu only waits on keys so it runs flat out, games get 600 instructions a second.
+    // [menu] and each game's entry in the ROM list can still override these.
+    let menu_config = EmulatorConfig {
+        fps: 0,
+        cycles_per_frame: 1,
+        idle_timeout: Some(IDLE_TIMEOUT),
+        ..EmulatorConfig::default()
+    };
+    let game_config = EmulatorConfig {
+        fps: 60,
+        cycles_per_frame: 10,
+        idle_timeout: Some(IDLE_TIMEOUT),
+        ..EmulatorConfig::default()
+    };
 
-    let register_value: u8 = chip8.v[1];
-    Ok(register_value)  // Return Register 1 (for when running my menu ROM)
-}
+    // Contrast picked last time with F + 0, if there is one
+    if let Some(&level) = std::fs::read(CONTRAST_FILE).unwrap_or_default().first() {
+        emulator.display_mut().set_contrast(level);
+    }
+    emulator.display_mut().boot_animation(Some(&BOOT_LOGO));
+    match emulator.chip8_mut().load_rpl(RPL_FILE) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => eprintln!("Couldn't load {}: {}", RPL_FILE, err),
+        _ => {}
+    }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let menu_file = "roms/menu-new.ch8";
-    let quirks = Quirks::new(true, false, false, true, true);
-    let debug = false;
-    let mut chip8 = Chip8::new(quirks);
-    chip8.debug = debug;
+    if diagnose {
+        // Check the timers can keep up at full speed on this hardware
+        let chip8 = emulator.chip8_mut();
+        chip8.load_rom_bytes(MENU_ROM)?;
+        let result = chip8.cycle_accurate_delay_test()?;
+        println!("Delay timer test: expected {}ms, took {}ms over {} ticks ({:+.1}% drift)",
+            result.expected_ms, result.actual_ms, result.tick_count, result.drift_pct);
+        if result.drift_pct > 5.0 {
+            println!("Timers are running slow, consider an interrupt driven timer or a lower cycle speed");
+        }
+        chip8.hard_reset();
+    }
 
     let mut menu_item: u8 = 0; // Save where you are in menu between the games
 
     // Infinitely loop to allow for swapping games without restarting
     loop {
-        chip8.load_rom(&menu_file.to_string())?;
-        let files: Vec<String> = chip8.load_file_to_memory("data/roms.txt".to_string(), MENU_LOAD_LOC);
-
-        chip8.v[1] = menu_item;
-        menu_item = run_game(&mut chip8, 0).unwrap();
-
-        chip8.reset();
-
-        let filename = &files[menu_item as usize];
-        let filename = format!("roms/{}", filename);
+        emulator.chip8_mut().set_platform(menu.platform);
+        emulator.chip8_mut().set_quirks(menu.quirks);
+        emulator.load_rom_bytes(MENU_ROM)?;
+        emulator.chip8_mut().load_menu_names(&game_names, MENU_LOAD_LOC)?;
+
+        emulator.chip8_mut().v[1] = menu_item;
+        menu_item = emulator.run_game_loop(&menu.emulator_config(&menu_config), GameLoopOptions::default())?.exit_code;
+
+        emulator.chip8_mut().hard_reset();
+
+        let Some(game) = games.get(menu_item as usize) else {
+            eprintln!("Menu picked game {}, but there are only {}", menu_item, games.len());
+            menu_item = 0;
+            continue;
+        };
+        let filename = format!("roms/{}", game.file);
+
+        let display = emulator.display_mut();
+        display.clear();
+        // A name the display can't show just means no marquee, the game still runs
+        if let Err(err) = display.draw_text(TITLE_PAGE, 0, &game.name) {
+            eprintln!("Couldn't show the title of {}: {}", game.name, err);
+        }
+        display.start_horizontal_scroll(ScrollDirection::Left, TITLE_PAGE, TITLE_PAGE, ScrollInterval::Frames2);
+        std::thread::sleep(TITLE_MARQUEE);
+        display.stop_scroll();
+        display.clear();
+
+        emulator.chip8_mut().set_platform(game.platform);
+        emulator.chip8_mut().set_quirks(game.quirks);
+        emulator.load_rom_file(&filename)?;
+        emulator.chip8_mut().enable_profiling(benchmark);
+        emulator.chip8_mut().enable_tracing(TRACE_LEN);
+        // A broken game shouldn't take the whole console down, go back to the menu instead
+        if let Err(err) = emulator.run_game_loop(&game.emulator_config(&game_config), GameLoopOptions::default()) {
+            eprintln!("{} stopped: {}", filename, err);
+            for entry in emulator.chip8().get_trace().into_iter().flatten() {
+                eprintln!("  #{} {:04X}: {:04X}  V={:02X?} I={:04X} SP={}",
+                    entry.cycle, entry.pc, entry.raw, entry.v, entry.i, entry.sp);
+            }
+        }
+        emulator.chip8_mut().enable_tracing(0);
+        if let Err(err) = emulator.chip8().save_rpl(RPL_FILE) {
+            eprintln!("Couldn't save {}: {}", RPL_FILE, err);
+        }
 
-        chip8.load_rom(&filename)?;
-        run_game(&mut chip8, 300).unwrap();
+        if benchmark {
+            // Where the time went for the game that just finished
+            println!("{}: {}", filename, emulator.chip8().execution_timeline());
+            println!("Opcode coverage: {:.1}%", emulator.chip8().opcode_coverage());
+            println!("{}", emulator.chip8().measure_cycle_jitter());
+            print!("{}", emulator.chip8().call_depth_chart());
+            if let Some(profile) = emulator.chip8().get_profile() {
+                print!("{}", profile.report());
+            }
+        }
 
-        chip8.reset();
+        emulator.chip8_mut().hard_reset();
     }
 }
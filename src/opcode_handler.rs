@@ -0,0 +1,27 @@
+use crate::chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+
+// Mutable view of the Chip8 internals handed to custom opcode handlers
+pub struct Chip8State<'a> {
+    pub memory: &'a mut [u8],
+    pub v: &'a mut [u8; 16],
+    pub i: &'a mut u16,
+    pub pc: &'a mut u16,
+    pub display: &'a mut [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    pub draw_flag: &'a mut bool
+}
+
+pub trait OpcodeHandler {
+    fn execute(&mut self, inst: &Instruction, chip8_state: &mut Chip8State) -> Result<(), Chip8Error>;
+}
+
+// Example handler that just prints a message when its opcode runs
+pub struct LoggingOpcodeHandler(pub String);
+
+impl OpcodeHandler for LoggingOpcodeHandler {
+    fn execute(&mut self, inst: &Instruction, chip8_state: &mut Chip8State) -> Result<(), Chip8Error> {
+        println!("{} (opcode: {:04X}, PC: 0x{:X})", self.0, inst.instruction, chip8_state.pc);
+        Ok(())
+    }
+}
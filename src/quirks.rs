@@ -1,19 +1,303 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShiftMode {
+    Chip8 { uses_vy: bool },
+    SuperChip10,
+    SuperChip11
+}
+
+impl ShiftMode {
+    // Whether 8XY6/8XYE shift VX in place rather than reading VY
+    pub fn uses_vx(&self) -> bool {
+        match self {
+            ShiftMode::Chip8 { uses_vy } => !uses_vy,
+            ShiftMode::SuperChip10 => false,
+            ShiftMode::SuperChip11 => true
+        }
+    }
+}
+
+// What FX1E does when I + VX overflows. The original interpreters never touch VF here,
+// only the Amiga CHIP-8 interpreter did, and Spacefight 2091! is the one game that needs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IOverflowBehavior {
+    // Set VF to 1 once I passes the end of memory, cleared to 0 otherwise (Amiga)
+    SetVf,
+    // Wrap without touching VF
+    Ignore,
+    // Stop at the last address in memory, VF is left alone
+    Saturate
+}
+
+// The interpreter family a ROM was written for, which decides the extra opcode families it
+// gets. Quirks still tweak the behaviour within a family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Platform {
+    Chip8,
+    Chip48,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+    // The usual quirks for the platform
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::chip8(),
+            Platform::Chip48 => Quirks::chip48(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip()
+        }
+    }
+
+    // Platform of a quirks preset, as used in the ROM list
+    pub fn from_preset_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" | "cosmac_vip" | "amiga" => Some(Platform::Chip8),
+            "chip48" => Some(Platform::Chip48),
+            "superchip" | "superchip10" | "superchip11" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quirks {
     pub load_store: bool,
-    pub shift: bool,
+    pub shift_mode: ShiftMode,
     pub jump: bool,
     pub vf_reset: bool,
-    pub clip: bool 
+    pub clip: bool,
+    pub display_wait: bool,
+    // Draws allowed between VBlanks, later ones are dropped (0 means unlimited)
+    pub max_draws_per_vblank: u8,
+    pub i_overflow: IOverflowBehavior
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::chip8()
+    }
+}
+
+/// Names each quirk it changes, anything left alone keeps the original CHIP-8 value
+///
+/// ```
+/// use chip8_embedded_emulator::chip8::Chip8;
+/// use chip8_embedded_emulator::quirks::Quirks;
+///
+/// let chip8 = Chip8::new(Quirks::builder().clip(false).build());
+/// ```
+///
+/// Chip8 only takes the finished Quirks, so leaving off build() doesn't compile:
+///
+/// ```compile_fail
+/// use chip8_embedded_emulator::chip8::Chip8;
+/// use chip8_embedded_emulator::quirks::Quirks;
+///
+/// let chip8 = Chip8::new(Quirks::builder().clip(false));
+/// ```
+pub struct QuirksBuilder {
+    quirks: Quirks
+}
+
+impl Default for QuirksBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QuirksBuilder {
+    pub fn new() -> Self {
+        QuirksBuilder { quirks: Quirks::chip8() }
+    }
+
+    pub fn load_store(mut self, val: bool) -> Self {
+        self.quirks.load_store = val;
+        self
+    }
+
+    // true shifts VX in place, false shifts VY into VX
+    pub fn shift(mut self, val: bool) -> Self {
+        self.quirks.shift_mode = ShiftMode::Chip8 { uses_vy: !val };
+        self
+    }
+
+    pub fn shift_mode(mut self, mode: ShiftMode) -> Self {
+        self.quirks.shift_mode = mode;
+        self
+    }
+
+    pub fn jump(mut self, val: bool) -> Self {
+        self.quirks.jump = val;
+        self
+    }
+
+    pub fn vf_reset(mut self, val: bool) -> Self {
+        self.quirks.vf_reset = val;
+        self
+    }
+
+    pub fn clip(mut self, val: bool) -> Self {
+        self.quirks.clip = val;
+        self
+    }
+
+    pub fn display_wait(mut self, val: bool) -> Self {
+        self.quirks.display_wait = val;
+        self
+    }
+
+    pub fn max_draws_per_vblank(mut self, max: u8) -> Self {
+        self.quirks.max_draws_per_vblank = max;
+        self
+    }
+
+    pub fn i_overflow(mut self, behavior: IOverflowBehavior) -> Self {
+        self.quirks.i_overflow = behavior;
+        self
+    }
+
+    pub fn build(self) -> Quirks {
+        self.quirks
+    }
 }
 
 impl Quirks {
+    pub fn builder() -> QuirksBuilder {
+        QuirksBuilder::new()
+    }
+
+    #[deprecated(note = "use `Quirks::builder()`, the five positional bools are easy to mix up")]
     pub fn new(ld: bool, shift: bool, jump: bool, vf_reset: bool, clip: bool) -> Self {
         Quirks {
             load_store: ld,
-            shift: shift,
-            jump: jump,
-            vf_reset: vf_reset,
-            clip: clip 
+            shift_mode: ShiftMode::Chip8 { uses_vy: !shift },
+            jump,
+            vf_reset,
+            clip,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    // Original Chip8 behaviour, without waiting for VBlank on draws
+    pub fn chip8() -> Self {
+        Quirks {
+            load_store: true,
+            shift_mode: ShiftMode::Chip8 { uses_vy: true },
+            jump: false,
+            vf_reset: false,
+            clip: false,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    // Original COSMAC VIP interpreter, including the wait for VBlank on every draw
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            display_wait: true,
+            ..Quirks::chip8()
+        }
+    }
+
+    // CHIP-48 on the HP-48, the interpreter SUPER-CHIP grew out of. Same quirks as
+    // SUPER-CHIP 1.0 but without the hi-res extensions.
+    pub fn chip48() -> Self {
+        Quirks {
+            load_store: false,
+            shift_mode: ShiftMode::Chip8 { uses_vy: false },
+            jump: true,
+            vf_reset: false,
+            clip: true,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    // The Amiga interpreter, original CHIP-8 apart from FX1E setting VF on overflow
+    pub fn amiga() -> Self {
+        Quirks {
+            i_overflow: IOverflowBehavior::SetVf,
+            ..Quirks::chip8()
         }
     }
+
+    // Modern SUPER-CHIP, which is what most SCHIP ROMs are written against
+    pub fn superchip() -> Self {
+        Quirks::superchip11()
+    }
+
+    pub fn superchip10() -> Self {
+        Quirks {
+            load_store: false,
+            shift_mode: ShiftMode::SuperChip10,
+            jump: true,
+            vf_reset: false,
+            clip: true,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    pub fn superchip11() -> Self {
+        Quirks {
+            load_store: false,
+            shift_mode: ShiftMode::SuperChip11,
+            jump: true,
+            vf_reset: false,
+            clip: true,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    // Octo's XO-CHIP behaviour, sprites wrap around the screen edges
+    pub fn xochip() -> Self {
+        Quirks {
+            load_store: true,
+            shift_mode: ShiftMode::Chip8 { uses_vy: true },
+            jump: false,
+            vf_reset: false,
+            clip: false,
+            display_wait: false,
+            max_draws_per_vblank: 0,
+            i_overflow: IOverflowBehavior::Ignore
+        }
+    }
+
+    // Preset by name, as used in the ROM list
+    pub fn from_preset_name(name: &str) -> Option<Quirks> {
+        match name {
+            "chip8" => Some(Quirks::chip8()),
+            "cosmac_vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "amiga" => Some(Quirks::amiga()),
+            "superchip" => Some(Quirks::superchip()),
+            "superchip10" => Some(Quirks::superchip10()),
+            "superchip11" => Some(Quirks::superchip11()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None
+        }
+    }
+
+    pub fn with_shift_mode(mut self, mode: ShiftMode) -> Quirks {
+        self.shift_mode = mode;
+        self
+    }
+
+    #[deprecated(note = "use `shift_mode` instead")]
+    pub fn shift(&self) -> bool {
+        self.shift_mode.uses_vx()
+    }
 }
@@ -0,0 +1,113 @@
+use crate::chip8::JitterStats;
+use crate::quirks::Quirks;
+use crate::rom_analyzer::RomFormat;
+
+// How sure generate_test_report is of its suggested quirks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfidenceLevel {
+    Low,
+    Medium,
+    High
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpWarningKind {
+    OutsideRom,
+    IntoFontset,
+    Misaligned
+}
+
+// A jump or call found by static analysis that probably isn't what the ROM meant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JumpWarning {
+    pub addr: u16,
+    pub target: u16,
+    pub kind: JumpWarningKind
+}
+
+// Counters from the test run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Chip8Stats {
+    pub cycles: u64,
+    pub frames_rendered: u64,
+    pub collisions: u64,
+    pub sound_ticks: u64,
+    pub max_observed_sp: usize,
+    pub spin_waits: u64
+}
+
+pub struct TestReport {
+    pub rom_hash: [u8; 32],
+    pub detected_format: RomFormat,
+    pub suggested_quirks: Quirks,
+    pub quirk_confidence: ConfidenceLevel,
+    pub jump_warnings: Vec<JumpWarning>,
+    pub opcode_coverage: f32,
+    pub runtime_stats: Chip8Stats,
+    pub final_display_hash: u64,
+    pub timing_jitter: JitterStats
+}
+
+impl TestReport {
+    pub fn rom_hash_hex(&self) -> String {
+        self.rom_hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn summary(&self) -> String {
+        let format_name = match self.detected_format {
+            RomFormat::Chip8 => "CHIP-8",
+            RomFormat::SuperChip => "SCHIP",
+            RomFormat::XoChip => "XO-CHIP"
+        };
+        let stats = &self.runtime_stats;
+
+        let mut out = format!("ROM {} looks like a {} program, its quirks were picked with {:?} confidence. ",
+            &self.rom_hash_hex()[..16], format_name, self.quirk_confidence);
+        out.push_str(&format!("It ran {} cycles, drew {} frames and reached a call depth of {}, covering {:.1}% of the opcodes. ",
+            stats.cycles, stats.frames_rendered, stats.max_observed_sp, self.opcode_coverage));
+        if self.jump_warnings.is_empty() {
+            out.push_str("No suspicious jumps were found.");
+        } else {
+            out.push_str(&format!("{} suspicious jumps were found.", self.jump_warnings.len()));
+        }
+        out
+    }
+
+    // Written by hand to keep serde_json out of the dependencies
+    pub fn to_json(&self) -> String {
+        let quirks = &self.suggested_quirks;
+        let stats = &self.runtime_stats;
+        let jitter = &self.timing_jitter;
+
+        let warnings: Vec<String> = self.jump_warnings.iter()
+            .map(|warning| format!(
+                "{{\"addr\": {}, \"target\": {}, \"kind\": \"{:?}\"}}",
+                warning.addr, warning.target, warning.kind
+            ))
+            .collect();
+
+        let mut out = String::from("{\n");
+        out.push_str(&format!("  \"rom_hash\": \"{}\",\n", self.rom_hash_hex()));
+        out.push_str(&format!("  \"detected_format\": \"{:?}\",\n", self.detected_format));
+        out.push_str(&format!(
+            "  \"suggested_quirks\": {{\"load_store\": {}, \"shift_mode\": \"{:?}\", \"jump\": {}, \"vf_reset\": {}, \"clip\": {}, \"display_wait\": {}, \"max_draws_per_vblank\": {}, \"i_overflow\": \"{:?}\"}},\n",
+            quirks.load_store, quirks.shift_mode, quirks.jump, quirks.vf_reset, quirks.clip,
+            quirks.display_wait, quirks.max_draws_per_vblank, quirks.i_overflow
+        ));
+        out.push_str(&format!("  \"quirk_confidence\": \"{:?}\",\n", self.quirk_confidence));
+        out.push_str(&format!("  \"jump_warnings\": [{}],\n", warnings.join(", ")));
+        out.push_str(&format!("  \"opcode_coverage\": {:.2},\n", self.opcode_coverage));
+        out.push_str(&format!(
+            "  \"runtime_stats\": {{\"cycles\": {}, \"frames_rendered\": {}, \"collisions\": {}, \"sound_ticks\": {}, \"max_observed_sp\": {}, \"spin_waits\": {}}},\n",
+            stats.cycles, stats.frames_rendered, stats.collisions, stats.sound_ticks, stats.max_observed_sp, stats.spin_waits
+        ));
+        // As a string, JSON numbers can't hold all 64 bits
+        out.push_str(&format!("  \"final_display_hash\": \"{:016X}\",\n", self.final_display_hash));
+        out.push_str(&format!(
+            "  \"timing_jitter\": {{\"mean_us\": {:.2}, \"stddev_us\": {:.2}, \"min_us\": {}, \"max_us\": {}, \"p99_us\": {}}}\n",
+            jitter.mean_us, jitter.stddev_us, jitter.min_us, jitter.max_us, jitter.p99_us
+        ));
+        out.push_str("}\n");
+        out
+    }
+}
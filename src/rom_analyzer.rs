@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::chip8::ROM_START;
+use crate::instruction::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+pub struct RomAnalysis {
+    // Every instruction reachable from the entry point, keyed by address
+    pub reachable: BTreeMap<u16, u16>,
+    pub schip_opcodes: Vec<u16>,
+    pub xochip_opcodes: Vec<u16>
+}
+
+impl RomAnalysis {
+    // XO-CHIP wins over SCHIP, XO-CHIP ROMs can use SCHIP opcodes as well
+    pub fn format(&self) -> RomFormat {
+        if !self.xochip_opcodes.is_empty() {
+            RomFormat::XoChip
+        } else if !self.schip_opcodes.is_empty() {
+            RomFormat::SuperChip
+        } else {
+            RomFormat::Chip8
+        }
+    }
+}
+
+pub struct RomAnalyzer;
+
+impl RomAnalyzer {
+    // Recursive descent from ROM_START following jumps, calls, skips and fall-throughs
+    pub fn analyze(rom: &[u8]) -> RomAnalysis {
+        Self::analyze_at(rom, ROM_START as u16)
+    }
+
+    // Same as analyze for a ROM loaded (and entered) at origin
+    pub fn analyze_at(rom: &[u8], origin: u16) -> RomAnalysis {
+        let mut reachable: BTreeMap<u16, u16> = BTreeMap::new();
+        let mut pending: Vec<u16> = vec![origin];
+
+        while let Some(addr) = pending.pop() {
+            if reachable.contains_key(&addr) {
+                continue;
+            }
+            let Some(raw) = Self::read_word(rom, origin, addr) else {
+                continue; // Outside of the ROM
+            };
+
+            reachable.insert(addr, raw);
+            pending.extend(Self::successors(addr, raw).into_iter().flatten());
+        }
+
+        let schip_opcodes = reachable.values().copied().filter(|&raw| Self::is_schip_opcode(raw)).collect();
+        let xochip_opcodes = reachable.values().copied().filter(|&raw| Self::is_xochip_opcode(raw)).collect();
+
+        RomAnalysis { reachable, schip_opcodes, xochip_opcodes }
+    }
+
+    // Successors of every reachable instruction, indirect jumps point at u16::MAX
+    pub fn control_flow_graph(rom: &[u8]) -> HashMap<u16, Vec<u16>> {
+        Self::control_flow_graph_at(rom, ROM_START as u16)
+    }
+
+    pub fn control_flow_graph_at(rom: &[u8], origin: u16) -> HashMap<u16, Vec<u16>> {
+        Self::analyze_at(rom, origin).reachable.iter()
+            .map(|(&addr, &raw)| {
+                let successors = Self::successors(addr, raw).into_iter()
+                    .map(|next| next.unwrap_or(u16::MAX))
+                    .collect();
+                (addr, successors)
+            })
+            .collect()
+    }
+
+    fn read_word(rom: &[u8], origin: u16, addr: u16) -> Option<u16> {
+        let offset = addr.checked_sub(origin)? as usize;
+        let high = *rom.get(offset)?;
+        let low = *rom.get(offset + 1)?;
+        Some((high as u16) << 8 | low as u16)
+    }
+
+    // Addresses execution can continue at after this instruction, None marks
+    // a successor that can't be known statically (BNNN)
+    pub fn successors(addr: u16, raw: u16) -> Vec<Option<u16>> {
+        let inst = Instruction::new(raw);
+        let next = addr.wrapping_add(2);
+        match inst.nibble {
+            0x0 if raw == 0x00EE || raw == 0x00FD => vec![], // Return / SCHIP exit
+            0x1 => vec![Some(inst.nnn)],
+            0x2 => vec![Some(inst.nnn), Some(next)],
+            0x3 | 0x4 => vec![Some(next), Some(next.wrapping_add(2))],
+            0x5 | 0x9 if inst.n == 0x0 => vec![Some(next), Some(next.wrapping_add(2))],
+            0xB => vec![None],
+            0xE if inst.nn == 0x9E || inst.nn == 0xA1 => vec![Some(next), Some(next.wrapping_add(2))],
+            0xF if raw == 0xF000 => vec![Some(next.wrapping_add(2))], // XO-CHIP 4 byte load
+            _ => vec![Some(next)]
+        }
+    }
+
+    pub fn is_schip_opcode(raw: u16) -> bool {
+        let inst = Instruction::new(raw);
+        match inst.nibble {
+            0x0 => inst.x == 0 && ((inst.nn & 0xF0) == 0xC0 || (0xFB..=0xFF).contains(&inst.nn)),
+            0xD => inst.n == 0,
+            0xF => inst.nn == 0x30 || inst.nn == 0x75 || inst.nn == 0x85,
+            _ => false
+        }
+    }
+
+    pub fn is_xochip_opcode(raw: u16) -> bool {
+        let inst = Instruction::new(raw);
+        match inst.nibble {
+            0x0 => inst.x == 0 && (inst.nn & 0xF0) == 0xD0,
+            0x5 => inst.n == 0x2 || inst.n == 0x3,
+            0xF => raw == 0xF000 || raw == 0xF002 || inst.nn == 0x01 || inst.nn == 0x3A,
+            _ => false
+        }
+    }
+}
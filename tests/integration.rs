@@ -0,0 +1,820 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chip8_embedded_emulator::chip8::{Chip8, CycleContext, RecoveryAction, RegWrite, StateChange, StepResult, Watchpoint};
+use chip8_embedded_emulator::display::{DisplayError, DisplayInterface};
+use chip8_embedded_emulator::emulator::EmulatorConfig;
+use chip8_embedded_emulator::error::Chip8Error;
+use chip8_embedded_emulator::instruction::Instruction;
+use chip8_embedded_emulator::quirks::{Platform, Quirks};
+
+const CHIP8_LOGO: &[u8] = include_bytes!("../roms/1-chip8-logo.ch8");
+const IBM_LOGO: &[u8] = include_bytes!("../roms/ibm-logo.ch8");
+const KEYPAD_TEST: &[u8] = include_bytes!("../roms/keypad-test.ch8");
+const TEST_OPCODE: &[u8] = include_bytes!("../roms/test-opcode.ch8");
+const CORAX_PLUS: &[u8] = include_bytes!("../roms/3-corax+.ch8");
+const FLAGS: &[u8] = include_bytes!("../roms/4-flags.ch8");
+
+// Timendus' splash screen, drawn before the ROM settles into its final loop
+const CHIP8_LOGO_FRAME: [&str; 32] = [
+    "................................................................",
+    "............#####.#....................#..........##............",
+    "..............#.....##.#...##..###...###.#..#..##..#............",
+    "..............#...#.#.#.#.#..#.#..#.#..#.#..#.#.................",
+    "..............#...#.#...#.####.#..#.#..#.#..#..#................",
+    "..............#...#.#...#.#....#..#.#..#.#..#...#...............",
+    "..............#...#.#...#..###.#..#..###..###.##................",
+    "................................................................",
+    "................................................................",
+    "...........#####...##.......##..#####...........#######.........",
+    "..........#######.###......###.#######.........###...###........",
+    ".........###...##.###......###.###..###.......###.....##........",
+    "........###.......###..........###...##.......###.....##........",
+    "........###..#.#..###.......##.###...##.......###.....##........",
+    "........###.......######...###.###...##........###...##.........",
+    "........###.#...#.#######..###.###...##.####....######..........",
+    "........###..###..###..###.###.###..###.####...###..###.........",
+    "........###.......###...##.###.#######........###....###........",
+    "........###.......###...##.###.######........###......##........",
+    "........###.......###...##.###.###...........###......##........",
+    "........###.......###...##.###.###.#.#...###.###......##........",
+    ".........###...##.###...##.###.###.###.....#.####....###........",
+    "..........#######.###...##.###.###...#...##...#########.........",
+    "...........#####..###...##.###.###...#.#.###...#######..........",
+    "................................................................",
+    "................................................................",
+    ".............###..##...##.#.......##......#.#....##.............",
+    "..............#..#..#.#...###....#...#..#...###.#..#............",
+    "..............#..####..#..#.......#..#..#.#.#...####............",
+    "..............#..#......#.#........#.#..#.#.#...#...............",
+    "..............#...###.##...##....##...###.#..##..###............",
+    "................................................................"
+];
+
+// The logo is drawn and the ROM spins on the jump to itself at 0x228
+const IBM_LOGO_FRAME: [&str; 32] = [
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "............########.#########...#####.........#####............",
+    "................................................................",
+    "............########.###########.######.......######............",
+    "................................................................",
+    "..............####.....###...###...#####.....#####..............",
+    "................................................................",
+    "..............####.....#######.....#######.#######..............",
+    "................................................................",
+    "..............####.....#######.....###.#######.###..............",
+    "................................................................",
+    "..............####.....###...###...###..#####..###..............",
+    "................................................................",
+    "............########.###########.#####...###...#####............",
+    "................................................................",
+    "............########.#########...#####....#....#####............",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    "................................................................"
+];
+
+// Key 5 highlighted after being pressed and released once
+const KEYPAD_TEST_FRAME: [&str; 32] = [
+    "................................................................",
+    "...#....####...####...####......................................",
+    "..##.......#......#...#.........................................",
+    "...#....####...####...#.........................................",
+    "...#....#.........#...#.........................................",
+    "..###...####...####...####......................................",
+    "................................................................",
+    "................................................................",
+    ".......######...................................................",
+    ".#..#..#....#..####...###.......................................",
+    ".#..#..#.####..#......#..#......................................",
+    ".####..#....#..####...#..#......................................",
+    "....#..####.#..#..#...#..#......................................",
+    "....#..#....#..####...###.......................................",
+    ".......######...................................................",
+    "................................................................",
+    "................................................................",
+    ".####...####...####...####......................................",
+    "....#...#..#...#..#...#.........................................",
+    "...#....####...####...####......................................",
+    "..#.....#..#......#...#.........................................",
+    "..#.....####...####...####......................................",
+    "................................................................",
+    "................................................................",
+    "................................................................",
+    ".####...####...###....####......................................",
+    ".#..#...#..#...#..#...#.........................................",
+    ".####...#..#...###....####......................................",
+    ".#..#...#..#...#..#...#.........................................",
+    ".#..#...####...###....#.........................................",
+    "................................................................",
+    "................................................................"
+];
+
+// Every opcode group reports OK
+const TEST_OPCODE_FRAME: [&str; 32] = [
+    "................................................................",
+    ".###.#.#..###.#.#......###.###..###.#.#.....###..##.###.#.#.....",
+    "..##..#...#.#.##.......#.#.##...#.#.##......###..#..#.#.##......",
+    "...#.#.#..#.#.#.#......#.#.#....#.#.#.#.....#.#...#.#.#.#.#.....",
+    ".###.#.#..###.#.#......###.###..###.#.#.....###..#..###.#.#.....",
+    "................................................................",
+    ".#.#.#.#..###.#.#......###.###..###.#.#.....###.###.###.#.#.....",
+    ".###..#...#.#.##.......###.#.#..#.#.##......###.#...#.#.##......",
+    "...#.#.#..#.#.#.#......#.#.#.#..#.#.#.#.....#.#.###.#.#.#.#.....",
+    "...#.#.#..###.#.#......###.###..###.#.#.....###.###.###.#.#.....",
+    "................................................................",
+    "..##.#.#..###.#.#......###.##...###.#.#.....###.###.###.#.#.....",
+    "..#...#...#.#.##.......###..#...#.#.##......###.##..#.#.##......",
+    "...#.#.#..#.#.#.#......#.#..#...#.#.#.#.....#.#.#...#.#.#.#.....",
+    "..#..#.#..###.#.#......###.###..###.#.#.....###.###.###.#.#.....",
+    "................................................................",
+    ".###.#.#..###.#.#......###.###..###.#.#.....###..##.###.#.#.....",
+    "...#..#...#.#.##.......###...#..#.#.##......#....#..#.#.##......",
+    "...#.#.#..#.#.#.#......#.#.##...#.#.#.#.....##....#.#.#.#.#.....",
+    "...#.#.#..###.#.#......###.###..###.#.#.....#....#..###.#.#.....",
+    "................................................................",
+    ".###.#.#..###.#.#......###.###..###.#.#.....###.###.###.#.#.....",
+    ".###..#...#.#.##.......###..##..#.#.##......#....##.#.#.##......",
+    "...#.#.#..#.#.#.#......#.#...#..#.#.#.#.....##....#.#.#.#.#.....",
+    ".###.#.#..###.#.#......###.###..###.#.#.....#...###.###.#.#.....",
+    "................................................................",
+    "..#..#.#..###.#.#......###.#.#..###.#.#.....##..#.#.###.#.#.....",
+    ".#.#..#...#.#.##.......###.###..#.#.##.......#...#..#.#.##......",
+    ".###.#.#..#.#.#.#......#.#...#..#.#.#.#......#..#.#.#.#.#.#.....",
+    ".#.#.#.#..###.#.#......###...#..###.#.#.....###.#.#.###.#.#.....",
+    "................................................................",
+    "................................................................"
+];
+
+// Timendus' corax+ opcode test, a tick next to every opcode
+const CORAX_PLUS_FRAME: [&str; 32] = [
+    "................................................................",
+    "..###.#.#.........###.#.#.........###.#.#.........###.###.......",
+    "...##..#...#.#......#..#...#.#....###.###..#.#....#...##...#.#..",
+    "....#.#.#..##.....##..#.#..##.....#.#...#..##.....##....#..##...",
+    "..###.#.#..#......###.#.#..#......###...#..#......#...##...#....",
+    "................................................................",
+    "..#.#.#.#.........###.###.........###.###.........###.###.......",
+    "..###..#...#.#....#.#.##...#.#....###.##...#.#....#....##..#.#..",
+    "....#.#.#..##.....#.#.#....##.....#.#...#..##.....##....#..##...",
+    "....#.#.#..#......###.###..#......###.##...#......#...###..#....",
+    "................................................................",
+    "..###.#.#.........###.###.........###.###.........###.###.......",
+    "..##...#...#.#....###.#.#..#.#....###...#..#.#....#...##...#.#..",
+    "....#.#.#..##.....#.#.#.#..##.....#.#..#...##.....##..#....##...",
+    "..##..#.#..#......###.###..#......###..#...#......#...###..#....",
+    "................................................................",
+    "..###.#.#.........###.##..........###..##.............#.#.......",
+    "....#..#...#.#....###..#...#.#....###.#....#.#....#.#..#...#.#..",
+    "...#..#.#..##.....#.#..#...##.....#.#.###..##.....#.#.#.#..##...",
+    "...#..#.#..#......###.###..#......###.###..#.......#..#.#..#....",
+    "................................................................",
+    "..###.#.#.........###.###.........###.###.......................",
+    "..###..#...#.#....###...#..#.#....###.##...#.#..................",
+    "....#.#.#..##.....#.#.##...##.....#.#.#....##...................",
+    "..##..#.#..#......###.###..#......###.###..#....................",
+    "................................................................",
+    "..##..#.#.........###.###.........###..##.............#.#...###.",
+    "...#...#...#.#....###..##..#.#....#...#....#.#....#.#.###.....#.",
+    "...#..#.#..##.....#.#...#..##.....##..###..##.....#.#...#...##..",
+    "..###.#.#..#......###.###..#......#...###..#.......#....#.#.###.",
+    "................................................................",
+    "................................................................"
+];
+
+// Timendus' flags test, a tick next to every VF result in the happy, carry and other rows
+const FLAGS_FRAME: [&str; 32] = [
+    "#.#..#..##..##..#.#...##....................###.................",
+    "###.#.#.#.#.#.#.#.#....#...#.#.#.#.#.#........#..#.#.#.#.#.#....",
+    "#.#.###.##..##...#.....#...##..##..##.......##...##..##..##.....",
+    "#.#.#.#.#...#....#....###..#...#...#........###..#...#...#......",
+    "................................................................",
+    "###...................#.#...................###.................",
+    ".##..#.#.#.#.#.#......###..#.#.#.#.#.#.#.#..##...#.#.#.#.#.#.#.#",
+    "..#..##..##..##.........#..##..##..##..##.....#..##..##..##..##.",
+    "###..#...#...#..........#..#...#...#...#....##...#...#...#...#..",
+    "................................................................",
+    "###...................###...................###.................",
+    "#....#.#.#.#.#.#........#..#.#.#.#.#.#.#.#..##...#.#.#.#.#.#....",
+    "###..##..##..##.........#..##..##..##..##...#....##..##..##.....",
+    "###..#...#...#..........#..#...#...#...#....###..#...#...#......",
+    "................................................................",
+    "................................................................",
+    "###..#..##..##..#.#...#.#...................###.................",
+    "#...#.#.#.#.#.#.#.#...###..#.#.#.#.#.#.#.#..##...#.#.#.#.#.#.#.#",
+    "#...###.##..##...#......#..##..##..##..##.....#..##..##..##..##.",
+    "###.#.#.#.#.#.#..#......#..#...#...#...#....##...#...#...#...#..",
+    "................................................................",
+    "###...................###...................###.................",
+    "#....#.#.#.#.#.#........#..#.#.#.#.#.#.#.#..##...#.#.#.#.#.#....",
+    "###..##..##..##.........#..##..##..##..##...#....##..##..##.....",
+    "###..#...#...#..........#..#...#...#...#....###..#...#...#......",
+    "................................................................",
+    "................................................................",
+    "###.###.#.#.###.##....###.###.........................#.#...###.",
+    "#.#..#..###.##..#.#...#...##...#.#.#.#............#.#.###.....#.",
+    "#.#..#..#.#.#...##....##..#....##..##.............#.#...#...##..",
+    "###..#..#.#.###.#.#...#...###..#...#...............#....#.#.###.",
+    "................................................................"
+];
+
+// Packed 8 pixels to a byte, the way quirk_score wants reference frames
+fn packed_frame_from_art(art: &[&str; 32]) -> [u8; 256] {
+    let mut packed = [0; 256];
+    for (index, pixel) in frame_from_art(art).iter().flatten().enumerate() {
+        if *pixel {
+            packed[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+    packed
+}
+
+fn frame_from_art(art: &[&str; 32]) -> [[bool; 64]; 32] {
+    let mut frame = [[false; 64]; 32];
+    for (row, line) in frame.iter_mut().zip(art.iter()) {
+        for (pixel, byte) in row.iter_mut().zip(line.bytes()) {
+            *pixel = byte == b'#';
+        }
+    }
+    frame
+}
+
+// Fixed seed, and the timers are never ticked so the runs don't depend on wall time
+fn run_rom(rom: &[u8], quirks: Quirks, cycles: u32) -> Chip8 {
+    let mut chip8 = Chip8::new_with_seed(quirks, [0; 32]);
+    chip8.load_rom_bytes(rom).unwrap();
+    chip8.cycle_n(cycles).unwrap();
+    chip8
+}
+
+#[test]
+fn chip8_logo_draws_the_splash_screen() {
+    let chip8 = run_rom(CHIP8_LOGO, Quirks::chip8(), 1000);
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&CHIP8_LOGO_FRAME));
+    assert_eq!(chip8.pc(), 0x24E);
+    assert_eq!(chip8.i(), 0x2F5);
+    assert_eq!(chip8.v, [0x30, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn ibm_logo_draws_the_logo() {
+    let chip8 = run_rom(IBM_LOGO, Quirks::chip8(), 1000);
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&IBM_LOGO_FRAME));
+    assert_eq!(chip8.pc(), 0x228);
+    assert_eq!(chip8.i(), 0x275);
+    assert_eq!(chip8.v, [0x31, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(chip8.sp(), 0);
+}
+
+#[test]
+fn run_for_duration_draws_the_ibm_logo() {
+    let mut chip8 = Chip8::new_with_seed(Quirks::chip8(), [0; 32]);
+    chip8.load_rom_bytes(IBM_LOGO).unwrap();
+
+    // The logo is done in a few dozen cycles, the rest is spent on the final jump to itself
+    let cycles = chip8.run_for_duration(Duration::from_millis(50), &EmulatorConfig::default()).unwrap();
+    assert!(cycles > 100);
+    assert_eq!(chip8.get_display(), &frame_from_art(&IBM_LOGO_FRAME));
+    assert_eq!(chip8.pc(), 0x228);
+}
+
+#[test]
+fn run_for_duration_stops_when_the_rom_exits() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    // 6005 7001 00EE: two instructions, then return from the top level
+    chip8.load_rom_bytes(&[0x60, 0x05, 0x70, 0x01, 0x00, 0xEE]).unwrap();
+
+    let cycles = chip8.run_for_duration(Duration::from_secs(10), &EmulatorConfig::default()).unwrap();
+    assert_eq!(cycles, 3);
+    assert_eq!(chip8.v[0], 0x06);
+}
+
+#[test]
+fn keypad_test_highlights_the_pressed_key() {
+    // The ROM shifts VX in place, like CHIP-48
+    let mut chip8 = run_rom(KEYPAD_TEST, Quirks::builder().shift(true).build(), 1000);
+
+    let mut keys = [false; 16];
+    keys[5] = true;
+    chip8.set_keypad(keys);
+    chip8.cycle_n(100).unwrap();
+    assert!(chip8.wait_for_release());
+
+    chip8.set_keypad([false; 16]);
+    chip8.cycle_n(1000).unwrap();
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&KEYPAD_TEST_FRAME));
+    // Stuck in the delay loop, since nothing ticks the delay timer down
+    assert_eq!(chip8.pc(), 0x246);
+    assert_eq!(chip8.i(), 0x222);
+    assert_eq!(chip8.v, [0x07, 0x08, 0x05, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10]);
+}
+
+#[test]
+fn test_opcode_rom_passes() {
+    let chip8 = run_rom(TEST_OPCODE, Quirks::chip8(), 1000);
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&TEST_OPCODE_FRAME));
+    assert_eq!(chip8.pc(), 0x3DC);
+    assert_eq!(chip8.i(), 0x202);
+    assert_eq!(chip8.v, [0x01, 0x03, 0x07, 0, 0, 0x2A, 0x89, 0xEC, 0x2C, 0x30, 0x34, 0x1A, 0, 0, 0, 0]);
+}
+
+#[test]
+fn corax_plus_rom_passes() {
+    let chip8 = run_rom(CORAX_PLUS, Quirks::chip8(), 5000);
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&CORAX_PLUS_FRAME));
+    assert_eq!(chip8.pc(), 0x49C);
+}
+
+#[test]
+fn flags_rom_passes() {
+    let chip8 = run_rom(FLAGS, Quirks::chip8(), 5000);
+
+    assert_eq!(chip8.get_display(), &frame_from_art(&FLAGS_FRAME));
+    assert_eq!(chip8.pc(), 0x542);
+}
+
+#[test]
+fn quirks_presets_match_the_platform_table() {
+    let chip8 = Quirks::chip8();
+    assert!(chip8.load_store && !chip8.shift_mode.uses_vx() && !chip8.jump && !chip8.vf_reset && !chip8.clip);
+
+    let chip48 = Quirks::chip48();
+    assert!(!chip48.load_store && chip48.shift_mode.uses_vx() && chip48.jump && !chip48.vf_reset && chip48.clip);
+
+    let superchip = Quirks::superchip();
+    assert!(!superchip.load_store && superchip.shift_mode.uses_vx() && superchip.jump && !superchip.vf_reset && superchip.clip);
+
+    let xochip = Quirks::xochip();
+    assert!(xochip.load_store && !xochip.shift_mode.uses_vx() && !xochip.jump && !xochip.vf_reset && !xochip.clip);
+}
+
+// cargo test stress_test -- --nocapture shows the error distribution
+#[test]
+fn stress_test_random_opcodes_never_panic() {
+    let chip8 = run_rom(IBM_LOGO, Quirks::chip8(), 100);
+    let before = chip8.save_state();
+
+    let result = chip8.stress_test(Duration::from_millis(200));
+    println!("{} opcodes, errors: {:?}, fontset intact: {}", result.total, result.errors, result.final_memory_ok);
+
+    assert!(result.total > 0);
+    assert_eq!(result.errors["panics_caught"], 0);
+    // The run happens on a copy
+    assert_eq!(chip8.save_state(), before);
+}
+
+#[test]
+fn calls_past_the_stack_overflow() {
+    // 0x200: CALL 0x200, forever
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.load_rom_bytes(&[0x22, 0x00]).unwrap();
+    chip8.cycle_n(16).unwrap();
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::StackOverflow)));
+}
+
+#[test]
+fn top_level_return_underflows_the_stack() {
+    // 0x200: RET with nothing on the stack
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+    chip8.set_wait_for_initial_ret(false);
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::StackUnderflow)));
+
+    // The menu's way of exiting is still there when enabled
+    chip8.set_pc(0x200);
+    chip8.set_wait_for_initial_ret(true);
+    assert!(matches!(chip8.step(), Ok(StepResult::ExitRom)));
+}
+
+#[test]
+fn extended_memory_reaches_the_last_address() {
+    let mut chip8 = Chip8::with_memory_size(32768).platform(Platform::XoChip).build().unwrap();
+    assert_eq!(chip8.memory_size(), 32768);
+    chip8.load_rom_bytes(&[
+        0xF0, 0x00, 0x7F, 0xFF, // LD I, 0x7FFF
+        0x60, 0x42,             // LD V0, 0x42
+        0xF0, 0x55,             // LD [I], V0
+        0xF0, 0x00, 0x7F, 0xFF, // LD I, 0x7FFF
+        0x60, 0x00,             // LD V0, 0x00
+        0xF0, 0x65              // LD V0, [I]
+    ]).unwrap();
+    chip8.cycle_n(6).unwrap();
+    assert_eq!(chip8.v[0], 0x42);
+}
+
+#[test]
+fn pc_at_the_end_of_memory_is_out_of_bounds() {
+    let mut chip8 = Chip8::with_memory_size(65536).build().unwrap();
+    chip8.set_pc(0xFFFE);
+    assert!(matches!(chip8.fetch(), Err(Chip8Error::PcOutOfBounds(0xFFFE))));
+    chip8.set_pc(0xFFFF);
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::PcOutOfBounds(0xFFFF))));
+}
+
+#[test]
+fn only_control_transfers_count_as_jumps() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.load_rom_bytes(&[
+        0x30, 0x00, // 0x200: SE V0, 0x00, always skips
+        0x00, 0x00, // 0x202: skipped
+        0x22, 0x08, // 0x204: CALL 0x208
+        0x12, 0x06, // 0x206: JP 0x206
+        0x00, 0xEE  // 0x208: RET
+    ]).unwrap();
+    let mut ctx = CycleContext { frame_parity: 0 };
+
+    let jumps: Vec<bool> = (0..4).map(|_| chip8.cycle_with_context(&mut ctx).unwrap().jumped).collect();
+    assert_eq!(jumps, [false, true, true, true]);
+}
+
+#[test]
+fn execution_records_show_memory_writes() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.load_rom_bytes(&[
+        0xA3, 0x00, // LD I, 0x300
+        0x60, 0x7B, // LD V0, 123
+        0xF0, 0x33  // LD B, V0
+    ]).unwrap();
+
+    let records = chip8.execute_all_from_pc(3);
+    assert_eq!(records[2].changed_fields, [
+        StateChange::Memory { addr: 0x300, old: 0, new: 1 },
+        StateChange::Memory { addr: 0x301, old: 0, new: 2 },
+        StateChange::Memory { addr: 0x302, old: 0, new: 3 }
+    ]);
+    // The run happened on a copy
+    assert_eq!(chip8.pc(), 0x200);
+}
+
+// The real thing, apart from 8XY4 forgetting to set the carry
+fn add_without_carry(inst: &Instruction, chip8: &mut Chip8) -> u8 {
+    if inst.nibble == 0x8 && inst.n == 0x4 {
+        chip8.v[inst.x] = chip8.v[inst.x].wrapping_add(chip8.v[inst.y]);
+        return 0;
+    }
+    chip8.execute(Instruction { extra: inst.extra, ..Instruction::new(inst.instruction) }).unwrap_or(0)
+}
+
+#[test]
+fn compare_to_reference_finds_the_carry_bug() {
+    let rom = [
+        0x60, 0xFF, // LD V0, 0xFF
+        0x61, 0x02, // LD V1, 0x02
+        0x80, 0x14, // ADD V0, V1
+        0x12, 0x06  // JP 0x206
+    ];
+    let divergences = Chip8::new(Quirks::chip8()).compare_to_reference(add_without_carry, &rom, 10);
+
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].cycle, 2);
+    assert_eq!(divergences[0].instruction, 0x8014);
+}
+
+#[test]
+fn auto_quirk_tune_picks_chip8_for_the_ibm_logo() {
+    let quirks = Chip8::auto_quirk_tune(IBM_LOGO, &packed_frame_from_art(&IBM_LOGO_FRAME), 1000).unwrap();
+    assert_eq!(quirks, Quirks::chip8());
+
+    assert!(matches!(Chip8::auto_quirk_tune(&[], &[0; 256], 1000), Err(Chip8Error::EmptyRom)));
+}
+
+#[test]
+fn save_state_round_trips_every_field() {
+    let mut chip8 = Chip8::with_memory_size(4096).quirks(Quirks::xochip()).platform(Platform::XoChip).build().unwrap();
+    chip8.load_rom_bytes_at(&[
+        0xF2, 0x01, // PLANE 2
+        0x60, 0x05, // LD V0, 5
+        0x61, 0x03, // LD V1, 3
+        0xF0, 0x29, // LD F, V0
+        0xD0, 0x15, // DRW V0, V1, 5
+        0xF1, 0x75, // LD R, V1
+        0x13, 0x0C  // JP 0x30C
+    ], 0x300).unwrap();
+    chip8.set_audio_pattern([0xAA; 16]);
+    chip8.cycle_n(7).unwrap();
+    let saved = chip8.save_state();
+
+    let mut restored = Chip8::new(Quirks::chip8());
+    restored.load_state(&saved).unwrap();
+
+    assert_eq!(restored.save_state(), saved);
+    assert_eq!(restored.platform(), Platform::XoChip);
+    assert_eq!(restored.quirks(), &Quirks::xochip());
+    assert_eq!(restored.plane_write_mask(), 2);
+    assert_eq!(restored.get_plane(1), chip8.get_plane(1));
+    assert!(restored.get_plane(1).iter().flatten().any(|&pixel| pixel));
+    assert_eq!(restored.rpl_flags()[..2], [5, 3]);
+    assert_eq!(restored.audio_pattern(), &[0xAA; 16]);
+    assert_eq!(restored.audio_pitch(), chip8.audio_pitch());
+    assert_eq!(restored.rom_load_address(), 0x300);
+    assert_eq!(restored.rom_size(), 14);
+    assert_eq!(restored.pc(), 0x30C);
+}
+
+static SLEPT_US: AtomicU64 = AtomicU64::new(0);
+
+fn mock_sleep(duration: Duration) {
+    SLEPT_US.fetch_add(duration.as_micros() as u64, Ordering::SeqCst);
+}
+
+#[test]
+fn display_wait_holds_every_draw_until_the_next_vblank() {
+    let mut chip8 = Chip8::new(Quirks::cosmac_vip());
+    chip8.set_sleep_fn(mock_sleep);
+    // D015 1200: draw, then jump back to the draw
+    chip8.load_rom_bytes(&[0xD0, 0x15, 0x12, 0x00]).unwrap();
+
+    chip8.cycle_n(200).unwrap();
+    assert!(SLEPT_US.load(Ordering::SeqCst) >= 100 * 16_000);
+}
+
+#[test]
+fn display_accessors_check_the_bounds() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+
+    chip8.set_display_pixel(63, 31, true).unwrap();
+    assert!(chip8.get_display_pixel(63, 31).unwrap());
+    assert!(!chip8.get_display_pixel(0, 0).unwrap());
+    assert!(matches!(chip8.get_display_pixel(64, 0), Err(Chip8Error::MemoryOutOfBounds(64))));
+    assert!(matches!(chip8.get_display_pixel(0, 32), Err(Chip8Error::MemoryOutOfBounds(2048))));
+    assert!(matches!(chip8.set_display_pixel(64, 0, true), Err(Chip8Error::MemoryOutOfBounds(64))));
+
+    let row = chip8.get_display_row(31).unwrap();
+    assert!(row[63] && row[..63].iter().all(|&pixel| !pixel));
+    assert!(matches!(chip8.get_display_row(32), Err(Chip8Error::MemoryOutOfBounds(2048))));
+}
+
+#[test]
+fn delay_timer_runs_out_after_a_second_of_irregular_ticks() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.delay_timer = 60;
+
+    // A busy game loop getting round to the timers at uneven intervals
+    let intervals_ms = [5, 40, 17, 3, 31, 9, 22, 60, 1, 12];
+    let mut elapsed = Duration::ZERO;
+    for &ms in intervals_ms.iter().cycle() {
+        chip8.tick_timers_with_debt(Duration::from_millis(ms));
+        elapsed += Duration::from_millis(ms);
+        if chip8.delay_timer == 0 {
+            break;
+        }
+    }
+
+    let tick = Duration::from_nanos(1_000_000_000 / 60);
+    assert!(elapsed.abs_diff(Duration::from_secs(1)) <= tick, "ran out after {:?}", elapsed);
+}
+
+#[test]
+fn late_timer_ticks_are_carried_as_debt() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.delay_timer = 10;
+
+    // Three ticks' worth at once, two more than one tick per call would give
+    chip8.tick_timers_with_debt(Duration::from_millis(50));
+    assert_eq!(chip8.delay_timer, 7);
+    assert_eq!(chip8.timer_debt(), 2);
+
+    // Nothing much was left over, so the next tick needs a full 16.7ms again
+    chip8.tick_timers_with_debt(Duration::from_millis(17));
+    assert_eq!(chip8.delay_timer, 6);
+    assert_eq!(chip8.timer_debt(), 0);
+}
+
+#[test]
+fn sound_timer_total_counts_every_tick_the_sound_was_on() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    // 6005 F018 6103 F118: sound for 5 ticks, then for 3 ticks
+    chip8.load_rom_bytes(&[0x60, 0x05, 0xF0, 0x18, 0x61, 0x03, 0xF1, 0x18]).unwrap();
+
+    chip8.cycle_n(2).unwrap();
+    for _ in 0..3 {
+        chip8.tick_timers();
+    }
+    assert_eq!(chip8.sound_timer_total(), 3);
+
+    // Setting it again before it ran out starts a new count from 3
+    chip8.cycle_n(2).unwrap();
+    for _ in 0..5 {
+        chip8.tick_timers();
+    }
+    assert_eq!(chip8.sound_timer, 0);
+    assert_eq!(chip8.sound_timer_total(), 6);
+    assert_eq!(chip8.sound_total_seconds(), 0.1);
+
+    chip8.reset();
+    assert_eq!(chip8.sound_timer_total(), 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn ascii_display_is_32_lines_of_64_characters() {
+    let chip8 = run_rom(IBM_LOGO, Quirks::chip8(), 1000);
+
+    let ascii = chip8.display_as_ascii();
+    let lines: Vec<&str> = ascii.lines().collect();
+    assert_eq!(lines.len(), 32);
+    for (line, art) in lines.iter().zip(IBM_LOGO_FRAME.iter()) {
+        assert_eq!(line.chars().count(), 64);
+        assert!(line.chars().zip(art.chars()).all(|(ch, pixel)| (ch == '█') == (pixel == '#')));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sixel_display_is_six_bands_of_64_columns() {
+    let chip8 = run_rom(IBM_LOGO, Quirks::chip8(), 1000);
+
+    let sixel = chip8.display_as_sixel();
+    let body = sixel.strip_prefix("\x1bPq").and_then(|rest| rest.strip_suffix("\x1b\\")).unwrap();
+    // 32 rows in bands of 6, the last one padded
+    let bands: Vec<&str> = body.split("#1").skip(2).collect();
+    assert_eq!(bands.len(), 6);
+    for band in bands {
+        assert_eq!(band.len(), 65);
+        assert!(band.ends_with('-'));
+    }
+}
+
+#[test]
+fn fill_region_only_addresses_the_region() {
+    let sequence = DisplayInterface::fill_region_sequence(0x25, 2, 3, 2, 0xFF).unwrap();
+    assert_eq!(sequence, vec![
+        ([0xB2, 0x05, 0x12], vec![0xFF; 3]),
+        ([0xB3, 0x05, 0x12], vec![0xFF; 3])
+    ]);
+
+    let clear = DisplayInterface::fill_region_sequence(0, 7, 128, 1, 0x00).unwrap();
+    assert_eq!(clear, vec![([0xB7, 0x00, 0x10], vec![0x00; 128])]);
+
+    assert!(matches!(DisplayInterface::fill_region_sequence(126, 0, 3, 1, 0xFF), Err(DisplayError::RegionOutOfBounds)));
+    assert!(matches!(DisplayInterface::fill_region_sequence(0, 7, 1, 2, 0xFF), Err(DisplayError::RegionOutOfBounds)));
+}
+
+fn run_all(chip8: &mut Chip8, raw: &[u16]) {
+    for &inst in raw {
+        chip8.execute(Instruction::new(inst)).unwrap();
+    }
+}
+
+#[test]
+fn overlapping_sprites_count_one_collision() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    // The font's 0 at (0, 0), then again at (2, 0) where the two overlap
+    run_all(&mut chip8, &[0xF029, 0x6100, 0x6200, 0xD125, 0x6102, 0xD125]);
+    assert_eq!(chip8.v[0xF], 1);
+    assert_eq!(chip8.pixel_collision_count(), 1);
+
+    // Further along at (20, 0), clear of both
+    run_all(&mut chip8, &[0x6114, 0xD125]);
+    assert_eq!(chip8.v[0xF], 0);
+    assert_eq!(chip8.pixel_collision_count(), 1);
+
+    chip8.reset();
+    assert_eq!(chip8.pixel_collision_count(), 0);
+}
+
+#[test]
+fn only_draws_that_toggle_pixels_change_the_display() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    // A 5 row sprite of zero bytes at 0x300 draws nothing
+    run_all(&mut chip8, &[0xA300, 0xD005]);
+    assert!(chip8.draw_flag);
+    assert!(!chip8.display_changed_since_last_render());
+
+    // The font's 0 does
+    run_all(&mut chip8, &[0xF029, 0xD005]);
+    assert!(chip8.display_changed_since_last_render());
+
+    chip8.mark_rendered();
+    assert!(!chip8.display_changed_since_last_render());
+
+    // Drawing it again erases it, which is a change too
+    run_all(&mut chip8, &[0xD005]);
+    assert!(chip8.display_changed_since_last_render());
+}
+
+#[test]
+fn display_diff_counts_the_pixels_a_redraw_erased() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    run_all(&mut chip8, &[0xF029, 0xD005]);
+    let prev = *chip8.get_display();
+    assert_eq!(chip8.display_diff(&prev), 0);
+
+    // F0 90 90 90 F0 has 14 pixels lit, all of them go out again
+    run_all(&mut chip8, &[0xD005]);
+    assert_eq!(chip8.display_diff(&prev), 14);
+}
+
+#[test]
+fn trace_exports_as_a_markdown_table_and_csv() {
+    let mut chip8 = Chip8::new_with_seed(Quirks::chip8(), [0; 32]);
+    chip8.enable_tracing(100);
+    chip8.load_rom_bytes(IBM_LOGO).unwrap();
+    chip8.cycle_n(60).unwrap();
+
+    let markdown = chip8.export_trace_as_markdown_table();
+    let table = markdown.strip_prefix("```markdown\n").and_then(|rest| rest.strip_suffix("```\n")).unwrap();
+    let rows: Vec<&str> = table.lines().collect();
+    assert!(rows[0].starts_with("| PC | Opcode | Mnemonic | V0 |"));
+    assert!(rows[0].ends_with("| I | SP |"));
+    // Header, separator and the last 50 of the 60 cycles
+    assert_eq!(rows.len(), 52);
+    let columns = rows[0].matches('|').count();
+    for row in &rows {
+        assert!(row.starts_with('|') && row.ends_with('|'));
+        assert_eq!(row.matches('|').count(), columns, "{}", row);
+    }
+
+    let csv = chip8.export_trace_as_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "cycle,pc,opcode,mnemonic,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i,sp");
+    assert_eq!(lines.len(), 61);
+    assert!(lines[1].contains(",0200,00E0,\"Cls\""), "{}", lines[1]);
+}
+
+#[test]
+fn register_trace_logs_every_write() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.enable_register_trace(8);
+    // 6542 6542: the second write leaves V5 as it was, but is still a write
+    chip8.load_rom_bytes(&[0x65, 0x42, 0x65, 0x42]).unwrap();
+    chip8.cycle_n(2).unwrap();
+
+    let trace: Vec<RegWrite> = chip8.register_trace().iter().copied().collect();
+    assert_eq!(trace, vec![
+        RegWrite { reg: 5, old_val: 0, new_val: 0x42, pc: 0x200 },
+        RegWrite { reg: 5, old_val: 0x42, new_val: 0x42, pc: 0x202 }
+    ]);
+}
+
+#[test]
+fn register_watchpoint_stops_cycle_on_every_matching_write() {
+    let mut chip8 = Chip8::new(Quirks::chip8());
+    chip8.register_write_watchpoint(5, 0x42);
+    // 6542 6542 6543: the repeat write still hits, the different value doesn't
+    chip8.load_rom_bytes(&[0x65, 0x42, 0x65, 0x42, 0x65, 0x43]).unwrap();
+
+    let hit = Watchpoint::Register { reg: 5, value: 0x42 };
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::WatchpointHit { watchpoint }) if watchpoint == hit));
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::WatchpointHit { watchpoint }) if watchpoint == hit));
+    assert!(chip8.cycle().is_ok());
+}
+
+#[test]
+fn reset_replays_the_seeded_random_sequence() {
+    let mut chip8 = Chip8::new_with_seed(Quirks::chip8(), [7; 32]);
+    // C0FF C1FF C2FF C3FF
+    chip8.load_rom_bytes(&[0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF]).unwrap();
+    chip8.cycle_n(4).unwrap();
+    let first_run = chip8.v;
+
+    chip8.reset();
+    assert_eq!(chip8.seed(), [7; 32]);
+    chip8.cycle_n(4).unwrap();
+    assert_eq!(chip8.v, first_run);
+}
+
+#[test]
+fn skipping_a_failed_instruction_steps_over_all_of_it() {
+    let mut chip8 = Chip8::with_memory_size(4096).platform(Platform::XoChip).build().unwrap();
+    chip8.set_error_recovery(|_, _| RecoveryAction::SkipInstruction);
+    // F000 FFFF points I outside 4KB of memory, then 6542
+    chip8.load_rom_bytes(&[0xF0, 0x00, 0xFF, 0xFF, 0x65, 0x42]).unwrap();
+
+    chip8.cycle().unwrap();
+    assert_eq!(chip8.pc(), 0x204);
+    chip8.cycle().unwrap();
+    assert_eq!(chip8.v[5], 0x42);
+}
+
+#[test]
+fn audio_waveform_draws_a_bar_for_every_set_pattern_bit() {
+    let mut chip8 = Chip8::new(Quirks::xochip());
+    let mut pattern = [0; 16];
+    pattern[0] = 0b1010_0000;
+    pattern[15] = 0x01;
+    chip8.set_audio_pattern(pattern);
+
+    let waveform = chip8.audio_waveform();
+    assert_eq!(waveform[..8], [0xFF, 0x80, 0xFF, 0x80, 0x80, 0x80, 0x80, 0x80]);
+    assert!(waveform[8..127].iter().all(|&byte| byte == 0x80));
+    assert_eq!(waveform[127], 0xFF);
+}
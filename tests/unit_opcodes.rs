@@ -0,0 +1,600 @@
+use chip8_embedded_emulator::chip8::Chip8;
+use chip8_embedded_emulator::error::Chip8Error;
+use chip8_embedded_emulator::instruction::Instruction;
+use chip8_embedded_emulator::quirks::{IOverflowBehavior, Platform, Quirks};
+
+// execute() runs a single instruction without fetching it, so the PC only moves on jumps
+// and skips and starts out at 0x200
+fn chip8() -> Chip8 {
+    Chip8::new_with_seed(Quirks::chip8(), [0; 32])
+}
+
+fn run(chip8: &mut Chip8, raw: u16) {
+    chip8.execute(Instruction::new(raw)).unwrap();
+}
+
+// Reads len bytes back through FX65, which leaves them in V0 onwards
+fn read_memory(chip8: &mut Chip8, addr: u16, len: usize) -> Vec<u8> {
+    run(chip8, 0xA000 | addr);
+    run(chip8, 0xF065 | ((len as u16 - 1) << 8));
+    chip8.v[..len].to_vec()
+}
+
+#[test]
+fn cls_00e0() {
+    let mut chip8 = chip8();
+    chip8.set_display_pixel(3, 4, true).unwrap();
+    run(&mut chip8, 0x00E0);
+    assert!(chip8.get_display().iter().flatten().all(|&pixel| !pixel));
+}
+
+#[test]
+fn ret_00ee() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0x2300);
+    run(&mut chip8, 0x00EE);
+    assert_eq!(chip8.pc(), 0x200);
+    assert_eq!(chip8.sp(), 0);
+}
+
+#[test]
+fn scroll_down_00cn() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.set_display_pixel(5, 0, true).unwrap();
+    run(&mut chip8, 0x00C2);
+    assert!(!chip8.get_display()[0][5]);
+    assert!(chip8.get_display()[2][5]);
+}
+
+#[test]
+fn scroll_right_00fb() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.set_display_pixel(5, 0, true).unwrap();
+    run(&mut chip8, 0x00FB);
+    assert!(!chip8.get_display()[0][5]);
+    assert!(chip8.get_display()[0][9]);
+}
+
+#[test]
+fn scroll_left_00fc() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.set_display_pixel(5, 0, true).unwrap();
+    run(&mut chip8, 0x00FC);
+    assert!(!chip8.get_display()[0][5]);
+    assert!(chip8.get_display()[0][1]);
+}
+
+#[test]
+fn lores_00fe() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    run(&mut chip8, 0x00FF);
+    run(&mut chip8, 0x00FE);
+    assert!(!chip8.is_hires());
+}
+
+#[test]
+fn hires_00ff() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    run(&mut chip8, 0x00FF);
+    assert!(chip8.is_hires());
+}
+
+#[test]
+fn jp_1nnn() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0x1345);
+    assert_eq!(chip8.pc(), 0x345);
+}
+
+#[test]
+fn call_2nnn() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0x2345);
+    assert_eq!(chip8.pc(), 0x345);
+    assert_eq!(chip8.sp(), 1);
+    assert_eq!(chip8.stack()[0], 0x200);
+}
+
+#[test]
+fn se_byte_3xnn() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0x42;
+    run(&mut chip8, 0x3143);
+    assert_eq!(chip8.pc(), 0x200);
+    run(&mut chip8, 0x3142);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn sne_byte_4xnn() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0x42;
+    run(&mut chip8, 0x4142);
+    assert_eq!(chip8.pc(), 0x200);
+    run(&mut chip8, 0x4143);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn se_reg_5xy0() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 7;
+    run(&mut chip8, 0x5120);
+    assert_eq!(chip8.pc(), 0x200);
+    chip8.v[2] = 7;
+    run(&mut chip8, 0x5120);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn store_range_5xy2() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    chip8.v[1..4].copy_from_slice(&[1, 2, 3]);
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0x5132);
+    assert_eq!(chip8.i(), 0x300);
+    assert_eq!(read_memory(&mut chip8, 0x300, 3), [1, 2, 3]);
+}
+
+#[test]
+fn load_range_5xy3() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    chip8.v[..3].copy_from_slice(&[4, 5, 6]);
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF255);
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0x5573);
+    assert_eq!(chip8.v[5..8], [4, 5, 6]);
+    assert_eq!(chip8.i(), 0x300);
+}
+
+#[test]
+fn range_5xy2_5xy3_single_register() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    chip8.v[4] = 9;
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0x5442);
+    run(&mut chip8, 0x5773);
+    assert_eq!(chip8.v[7], 9);
+    assert_eq!(chip8.i(), 0x300);
+}
+
+#[test]
+fn range_5xy2_5xy3_backwards_is_invalid() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    run(&mut chip8, 0xA300);
+    assert!(matches!(chip8.execute(Instruction::new(0x5312)), Err(Chip8Error::InvalidOpcode(0x5312))));
+    assert!(matches!(chip8.execute(Instruction::new(0x5313)), Err(Chip8Error::InvalidOpcode(0x5313))));
+}
+
+#[test]
+fn range_5xy2_5xy3_need_xo_chip() {
+    let mut chip8 = chip8();
+    assert!(matches!(chip8.execute(Instruction::new(0x5132)), Err(Chip8Error::InvalidOpcode(0x5132))));
+    assert!(matches!(chip8.execute(Instruction::new(0x5133)), Err(Chip8Error::InvalidOpcode(0x5133))));
+}
+
+#[test]
+fn ld_byte_6xnn() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0x6A42);
+    assert_eq!(chip8.v[0xA], 0x42);
+}
+
+#[test]
+fn add_byte_7xnn() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0xFF;
+    run(&mut chip8, 0x7102);
+    // Wraps without touching VF
+    assert_eq!(chip8.v[1], 0x01);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn ld_reg_8xy0() {
+    let mut chip8 = chip8();
+    chip8.v[2] = 0x42;
+    run(&mut chip8, 0x8120);
+    assert_eq!(chip8.v[1], 0x42);
+}
+
+#[test]
+fn or_8xy1() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0b1100;
+    chip8.v[2] = 0b1010;
+    run(&mut chip8, 0x8121);
+    assert_eq!(chip8.v[1], 0b1110);
+}
+
+#[test]
+fn and_8xy2() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0b1100;
+    chip8.v[2] = 0b1010;
+    run(&mut chip8, 0x8122);
+    assert_eq!(chip8.v[1], 0b1000);
+}
+
+#[test]
+fn xor_8xy3() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0b1100;
+    chip8.v[2] = 0b1010;
+    run(&mut chip8, 0x8123);
+    assert_eq!(chip8.v[1], 0b0110);
+}
+
+#[test]
+fn add_reg_8xy4() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0xFF;
+    chip8.v[2] = 0x02;
+    run(&mut chip8, 0x8124);
+    assert_eq!(chip8.v[1], 0x01);
+    assert_eq!(chip8.v[0xF], 1);
+    run(&mut chip8, 0x8124);
+    assert_eq!(chip8.v[1], 0x03);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn sub_8xy5() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 5;
+    chip8.v[2] = 7;
+    run(&mut chip8, 0x8125);
+    assert_eq!(chip8.v[1], 0xFE);
+    assert_eq!(chip8.v[0xF], 0);
+    chip8.v[2] = 0x0E;
+    run(&mut chip8, 0x8125);
+    assert_eq!(chip8.v[1], 0xF0);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn shr_8xy6() {
+    let mut chip8 = chip8();
+    // Original CHIP-8 shifts VY into VX
+    chip8.v[2] = 0b0000_0101;
+    run(&mut chip8, 0x8126);
+    assert_eq!(chip8.v[1], 0b0000_0010);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn subn_8xy7() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 5;
+    chip8.v[2] = 7;
+    run(&mut chip8, 0x8127);
+    assert_eq!(chip8.v[1], 2);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn shl_8xye() {
+    let mut chip8 = chip8();
+    chip8.v[2] = 0b1000_0001;
+    run(&mut chip8, 0x812E);
+    assert_eq!(chip8.v[1], 0b0000_0010);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn sne_reg_9xy0() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0x9120);
+    assert_eq!(chip8.pc(), 0x200);
+    chip8.v[2] = 1;
+    run(&mut chip8, 0x9120);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn ld_i_annn() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0xA123);
+    assert_eq!(chip8.i(), 0x123);
+}
+
+#[test]
+fn jp_offset_bnnn() {
+    let mut chip8 = chip8();
+    chip8.v[0] = 4;
+    chip8.v[3] = 8;
+    run(&mut chip8, 0xB300);
+    assert_eq!(chip8.pc(), 0x304);
+}
+
+#[test]
+fn rnd_cxnn() {
+    let mut chip8 = chip8();
+    run(&mut chip8, 0xC10F);
+    assert_eq!(chip8.v[1] & 0xF0, 0);
+    chip8.v[2] = 0xFF;
+    run(&mut chip8, 0xC200);
+    assert_eq!(chip8.v[2], 0);
+}
+
+#[test]
+fn drw_dxyn() {
+    let mut chip8 = chip8();
+    // The font's 0, F0 90 90 90 F0
+    run(&mut chip8, 0xF029);
+    run(&mut chip8, 0xD015);
+    let display = chip8.get_display();
+    assert!(display[0][..4].iter().all(|&pixel| pixel));
+    assert!(display[1][0] && !display[1][1] && display[1][3]);
+    assert_eq!(chip8.v[0xF], 0);
+
+    // Drawing it again erases it and reports the collision
+    run(&mut chip8, 0xD015);
+    assert!(chip8.get_display().iter().flatten().all(|&pixel| !pixel));
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn skp_ex9e() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 3;
+    run(&mut chip8, 0xE19E);
+    assert_eq!(chip8.pc(), 0x200);
+    let mut keys = [false; 16];
+    keys[3] = true;
+    chip8.set_keypad(keys);
+    run(&mut chip8, 0xE19E);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn skp_ex9e_past_the_last_key() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 16;
+    assert!(chip8.execute(Instruction::new(0xE19E)).is_err());
+    assert!(chip8.execute(Instruction::new(0xE1A1)).is_err());
+}
+
+#[test]
+fn sknp_exa1() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 3;
+    run(&mut chip8, 0xE1A1);
+    assert_eq!(chip8.pc(), 0x202);
+    let mut keys = [false; 16];
+    keys[3] = true;
+    chip8.set_keypad(keys);
+    run(&mut chip8, 0xE1A1);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn ld_i_long_f000() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    chip8.execute(Instruction { extra: Some(0x0ABC), ..Instruction::new(0xF000) }).unwrap();
+    assert_eq!(chip8.i(), 0x0ABC);
+}
+
+#[test]
+fn f000_is_two_bytes_outside_xo_chip() {
+    let mut chip8_only = chip8();
+    chip8_only.load_rom_bytes(&[0xF0, 0x00, 0x0A, 0xBC]).unwrap();
+    assert_eq!(chip8_only.fetch().unwrap().extra, None);
+    assert_eq!(chip8_only.pc(), 0x202);
+
+    let mut xochip = chip8();
+    xochip.set_platform(Platform::XoChip);
+    xochip.load_rom_bytes(&[0xF0, 0x00, 0x0A, 0xBC]).unwrap();
+    assert_eq!(xochip.fetch().unwrap().extra, Some(0x0ABC));
+    assert_eq!(xochip.pc(), 0x204);
+}
+
+#[test]
+fn plane_fn01() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::XoChip);
+    run(&mut chip8, 0xF201);
+    assert_eq!(chip8.plane_write_mask(), 2);
+}
+
+#[test]
+fn ld_vx_dt_fx07() {
+    let mut chip8 = chip8();
+    chip8.delay_timer = 0x20;
+    run(&mut chip8, 0xF107);
+    assert_eq!(chip8.v[1], 0x20);
+}
+
+#[test]
+fn ld_key_fx0a() {
+    let mut chip8 = chip8();
+    // Nothing pressed, the PC is wound back so FX0A runs again
+    run(&mut chip8, 0xF10A);
+    assert_eq!(chip8.pc(), 0x1FE);
+
+    let mut keys = [false; 16];
+    keys[7] = true;
+    chip8.set_keypad(keys);
+    chip8.set_pc(0x200);
+    run(&mut chip8, 0xF10A);
+    assert_eq!(chip8.pc(), 0x1FE);
+    assert!(chip8.wait_for_release());
+
+    // The key only counts once it's let go
+    chip8.set_keypad([false; 16]);
+    chip8.set_pc(0x200);
+    run(&mut chip8, 0xF10A);
+    assert_eq!(chip8.pc(), 0x200);
+    assert_eq!(chip8.v[1], 7);
+}
+
+#[test]
+fn ld_dt_vx_fx15() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xF115);
+    assert_eq!(chip8.delay_timer, 0x20);
+}
+
+#[test]
+fn ld_st_vx_fx18() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xF118);
+    assert_eq!(chip8.sound_timer, 0x20);
+}
+
+#[test]
+fn add_i_fx1e() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0x10;
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0x310);
+}
+
+#[test]
+fn add_i_fx1e_set_vf() {
+    let mut chip8 = Chip8::new(Quirks::amiga());
+    chip8.v[0xF] = 0x55;
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xAFD0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0xFF0);
+    assert_eq!(chip8.v[0xF], 0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0x1010);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn add_i_fx1e_saturate() {
+    let mut chip8 = chip8();
+    chip8.set_i_overflow_behavior(IOverflowBehavior::Saturate);
+    chip8.v[0xF] = 0x55;
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xAFF0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0xFFF);
+    assert_eq!(chip8.v[0xF], 0x55);
+}
+
+#[test]
+fn add_i_fx1e_leaves_vf_alone_with_the_quirk_off() {
+    let mut chip8 = chip8();
+    chip8.v[0xF] = 0x55;
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.v[0xF], 0x55);
+    // Past 0xFFF, which the Amiga quirk would flag
+    run(&mut chip8, 0xAFF0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0x1010);
+    assert_eq!(chip8.v[0xF], 0x55);
+}
+
+#[test]
+fn add_i_fx1e_overflows_at_the_end_of_extended_memory() {
+    let quirks = Quirks::builder().i_overflow(IOverflowBehavior::SetVf).build();
+    let mut chip8 = Chip8::with_memory_size(65536).quirks(quirks).build().unwrap();
+    chip8.v[1] = 0x20;
+    run(&mut chip8, 0xAFF0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0x1010);
+    assert_eq!(chip8.v[0xF], 0);
+
+    chip8.set_i_overflow_behavior(IOverflowBehavior::Saturate);
+    run(&mut chip8, 0xAFF0);
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i(), 0x1010);
+}
+
+#[test]
+fn ld_font_fx29() {
+    let mut chip8 = chip8();
+    chip8.v[1] = 0xA;
+    run(&mut chip8, 0xF129);
+    assert_eq!(chip8.i(), 0x50 + 0xA * 5);
+}
+
+#[test]
+fn ld_big_font_fx30() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.v[1] = 3;
+    run(&mut chip8, 0xF130);
+    assert_eq!(chip8.i(), 0xA0 + 3 * 10);
+}
+
+#[test]
+fn bcd_fx33() {
+    let mut chip8 = chip8();
+    chip8.v[4] = 123;
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF433);
+    assert_eq!(read_memory(&mut chip8, 0x300, 3), [1, 2, 3]);
+}
+
+#[test]
+fn store_fx55() {
+    let mut chip8 = chip8();
+    chip8.v[..3].copy_from_slice(&[7, 8, 9]);
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF255);
+    // Original CHIP-8 leaves I just past the last register
+    assert_eq!(chip8.i(), 0x303);
+    chip8.v = [0; 16];
+    assert_eq!(read_memory(&mut chip8, 0x300, 3), [7, 8, 9]);
+}
+
+#[test]
+fn load_fx65() {
+    let mut chip8 = chip8();
+    chip8.v[..2].copy_from_slice(&[7, 8]);
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF155);
+    chip8.v = [0; 16];
+    run(&mut chip8, 0xA300);
+    run(&mut chip8, 0xF165);
+    assert_eq!(chip8.v[..2], [7, 8]);
+    assert_eq!(chip8.i(), 0x302);
+}
+
+#[test]
+fn store_flags_fx75() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.v[..3].copy_from_slice(&[1, 2, 3]);
+    run(&mut chip8, 0xF275);
+    assert_eq!(chip8.rpl_flags()[..3], [1, 2, 3]);
+}
+
+#[test]
+fn load_flags_fx85() {
+    let mut chip8 = chip8();
+    chip8.set_platform(Platform::SuperChip);
+    chip8.v[..3].copy_from_slice(&[1, 2, 3]);
+    run(&mut chip8, 0xF275);
+    chip8.v = [0; 16];
+    run(&mut chip8, 0xF285);
+    assert_eq!(chip8.v[..3], [1, 2, 3]);
+}
+
+#[test]
+fn unknown_opcode_is_an_error() {
+    let mut chip8 = chip8();
+    assert!(chip8.execute(Instruction::new(0x8128)).is_err());
+    assert!(chip8.execute(Instruction::new(0xF1FF)).is_err());
+}